@@ -0,0 +1,199 @@
+//! Reads the 16-bit PCM WAV that ffmpeg extracts and estimates where speech
+//! is from frame energy, so segments over near-silent audio can be dropped
+//! without another model pass. Music and noise count as speech here, which is
+//! why the review report and `sync_subtitles` only use it when asked to.
+
+use anyhow::{bail, Context, Result};
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+/// Analysis frame length.
+pub const FRAME_MS: i64 = 30;
+
+/// RMS level (fraction of full scale, about -40 dBFS) above which a frame
+/// counts as speech. Extraction loudness-normalises to -16 LUFS, so speech
+/// sits well above it.
+const SPEECH_RMS: f64 = 0.01;
+
+/// Which [`FRAME_MS`] frames of a recording carry speech.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpeechMap {
+    voiced: Vec<bool>,
+}
+
+impl SpeechMap {
+    pub fn load(path: &Path) -> Result<Self> {
+        let file = File::open(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        Self::read(BufReader::new(file)).with_context(|| format!("Invalid WAV file {}", path.display()))
+    }
+
+    pub fn read(mut reader: impl Read) -> Result<Self> {
+        let mut header = [0; 12];
+        reader.read_exact(&mut header)?;
+        if &header[..4] != b"RIFF" || &header[8..] != b"WAVE" {
+            bail!("not a RIFF/WAVE file");
+        }
+        let mut format = None;
+        loop {
+            let mut chunk = [0; 8];
+            reader.read_exact(&mut chunk)?;
+            let size = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]) as usize;
+            match &chunk[..4] {
+                b"fmt " => {
+                    let mut fmt = vec![0; size + size % 2];
+                    reader.read_exact(&mut fmt)?;
+                    if size < 16 || u16::from_le_bytes([fmt[0], fmt[1]]) != 1 || fmt[14..16] != [16, 0] {
+                        bail!("expected 16-bit PCM");
+                    }
+                    let channels = u16::from_le_bytes([fmt[2], fmt[3]]).max(1) as usize;
+                    let rate = u32::from_le_bytes([fmt[4], fmt[5], fmt[6], fmt[7]]) as usize;
+                    format = Some((channels, rate));
+                }
+                b"data" => {
+                    let Some((channels, rate)) = format else {
+                        bail!("data chunk before fmt chunk");
+                    };
+                    // ffmpeg streaming to a pipe leaves the size unset.
+                    let size = if size == u32::MAX as usize || size == 0 { usize::MAX } else { size };
+                    return Ok(Self::analyse(reader.take(size as u64), channels, rate));
+                }
+                _ => {
+                    std::io::copy(&mut (&mut reader).take((size + size % 2) as u64), &mut std::io::sink())?;
+                }
+            }
+        }
+    }
+
+    fn analyse(mut data: impl Read, channels: usize, rate: usize) -> Self {
+        let frame_bytes = (rate * FRAME_MS as usize / 1000).max(1) * channels * 2;
+        let mut frame = vec![0; frame_bytes];
+        let mut voiced = Vec::new();
+        loop {
+            let mut filled = 0;
+            while filled < frame_bytes {
+                match data.read(&mut frame[filled..]) {
+                    Ok(0) | Err(_) => break,
+                    Ok(read) => filled += read,
+                }
+            }
+            let samples = frame[..filled - filled % 2]
+                .chunks_exact(2)
+                .map(|bytes| f64::from(i16::from_le_bytes([bytes[0], bytes[1]])) / 32768.0)
+                .collect::<Vec<_>>();
+            if samples.is_empty() {
+                break;
+            }
+            let rms = (samples.iter().map(|sample| sample * sample).sum::<f64>() / samples.len() as f64).sqrt();
+            voiced.push(rms > SPEECH_RMS);
+            if filled < frame_bytes {
+                break;
+            }
+        }
+        Self { voiced }
+    }
+
+    /// Share of the frames between `start_ms` and `end_ms` that carry speech;
+    /// `0` for a span outside the recording.
+    pub fn speech_ratio(&self, start_ms: i64, end_ms: i64) -> f64 {
+        let first = (start_ms.max(0) / FRAME_MS) as usize;
+        let last = ((end_ms + FRAME_MS - 1) / FRAME_MS).max(0) as usize;
+        let frames = self.voiced.get(first..last.min(self.voiced.len())).unwrap_or_default();
+        if frames.is_empty() {
+            return 0.0;
+        }
+        frames.iter().filter(|voiced| **voiced).count() as f64 / frames.len() as f64
+    }
+
+    /// Runs of speech frames as `(start_ms, end_ms)`.
+    pub fn regions(&self) -> Vec<(i64, i64)> {
+        let mut regions: Vec<(i64, i64)> = Vec::new();
+        for (index, _) in self.voiced.iter().enumerate().filter(|(_, voiced)| **voiced) {
+            let start = index as i64 * FRAME_MS;
+            match regions.last_mut() {
+                Some(last) if last.1 == start => last.1 = start + FRAME_MS,
+                _ => regions.push((start, start + FRAME_MS)),
+            }
+        }
+        regions
+    }
+}
+
+/// A 16 kHz mono WAV of `spans` (`(ms, amplitude)`) of a constant tone.
+#[cfg(test)]
+pub fn test_wav(spans: &[(i64, i16)]) -> Vec<u8> {
+    let samples = spans
+        .iter()
+        .flat_map(|&(ms, amplitude)| (0..ms * 16).map(move |index| if index % 2 == 0 { amplitude } else { -amplitude }))
+        .flat_map(i16::to_le_bytes)
+        .collect::<Vec<_>>();
+    let mut wav = Vec::new();
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + samples.len() as u32).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&[1, 0, 1, 0]);
+    wav.extend_from_slice(&16_000u32.to_le_bytes());
+    wav.extend_from_slice(&32_000u32.to_le_bytes());
+    wav.extend_from_slice(&[2, 0, 16, 0]);
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&(samples.len() as u32).to_le_bytes());
+    wav.extend_from_slice(&samples);
+    wav
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_speech_frames() {
+        let map = SpeechMap::read(&test_wav(&[(300, 8000), (600, 10), (300, 8000)])[..]).unwrap();
+        assert_eq!(map.voiced.len(), 40);
+        assert_eq!(map.speech_ratio(0, 300), 1.0);
+        assert_eq!(map.speech_ratio(300, 900), 0.0);
+        assert_eq!(map.speech_ratio(0, 1200), 0.5);
+        assert_eq!(map.speech_ratio(5000, 6000), 0.0);
+        assert_eq!(map.speech_ratio(-100, -50), 0.0);
+        assert_eq!(map.regions(), [(0, 300), (900, 1200)]);
+    }
+
+    #[test]
+    fn skips_unknown_chunks_and_reads_unsized_data() {
+        let wav = test_wav(&[(90, 8000)]);
+        let mut with_list = wav[..12].to_vec();
+        with_list.extend_from_slice(b"LIST\x03\x00\x00\x00abc\x00");
+        with_list.extend_from_slice(&wav[12..]);
+        let map = SpeechMap::read(&with_list[..]).unwrap();
+        assert_eq!(map.voiced, [true; 3]);
+
+        let mut streamed = wav.clone();
+        streamed[40..44].copy_from_slice(&u32::MAX.to_le_bytes());
+        streamed.extend_from_slice(&[0; 3]);
+        assert_eq!(SpeechMap::read(&streamed[..]).unwrap().voiced, [true, true, true, false]);
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("clip.wav");
+        std::fs::write(&path, "pcm").unwrap();
+        let err = SpeechMap::load(&path).unwrap_err();
+        assert_eq!(err.to_string(), format!("Invalid WAV file {}", path.display()));
+        let err = SpeechMap::load(&temp.path().join("missing.wav")).unwrap_err();
+        assert!(err.to_string().starts_with("Failed to read"), "{err}");
+
+        let wav = test_wav(&[(30, 0)]);
+        let mut float = wav.clone();
+        float[20] = 3;
+        assert!(SpeechMap::read(&float[..]).is_err());
+        let mut not_wave = wav.clone();
+        not_wave[8] = b'X';
+        assert!(SpeechMap::read(&not_wave[..]).is_err());
+        let data_first = [&wav[..12], &wav[36..]].concat();
+        assert!(SpeechMap::read(&data_first[..]).is_err());
+
+        std::fs::write(&path, &wav).unwrap();
+        assert_eq!(SpeechMap::load(&path).unwrap().voiced, [false]);
+    }
+}
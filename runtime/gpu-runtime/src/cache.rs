@@ -0,0 +1,251 @@
+//! Local cache of extracted audio and raw whisper-cli transcripts. Audio is
+//! keyed by the media's content hash; transcripts additionally by the models
+//! and decode settings. Wrapping, dedup and output formats are applied after
+//! decoding, so changing them re-renders subtitles from the cache. It is only
+//! used when a request asks for it, and never pruned on its own: a WAV per
+//! media file adds up, so `cache_clear` empties it.
+
+use crate::manifest::Fingerprint;
+use crate::TranscribeConfig;
+use anyhow::{Context, Result};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tempfile::TempPath;
+use walkdir::WalkDir;
+
+/// The cache's subdirectories; anything else in the directory is left alone.
+const ENTRY_DIRS: &[&str] = &["audio", "transcripts"];
+
+#[derive(Debug, Clone)]
+pub struct Cache {
+    dir: PathBuf,
+}
+
+impl Cache {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    pub fn audio(&self, key: &str) -> PathBuf {
+        self.dir.join("audio").join(format!("{key}.wav"))
+    }
+
+    pub fn transcript(&self, key: &str) -> PathBuf {
+        self.dir.join("transcripts").join(format!("{key}.json"))
+    }
+
+    /// Deletes every cached entry, returning how many files and bytes went.
+    pub fn clear(&self) -> Result<(u64, u64)> {
+        let (mut files, mut bytes) = (0, 0);
+        for dir in ENTRY_DIRS.iter().map(|name| self.dir.join(name)).filter(|dir| dir.is_dir()) {
+            for entry in WalkDir::new(&dir) {
+                let entry = entry?;
+                if entry.file_type().is_file() {
+                    files += 1;
+                    bytes += entry.metadata()?.len();
+                }
+            }
+            fs::remove_dir_all(&dir).with_context(|| format!("Failed to remove {}", dir.display()))?;
+        }
+        Ok((files, bytes))
+    }
+}
+
+/// The cache directory for a request: none unless it passes `cache: true` or
+/// a `cache_dir`, and none with `cache: false` either way.
+pub fn resolve_dir(enabled: Option<bool>, dir: Option<&str>) -> Option<PathBuf> {
+    match (enabled, dir) {
+        (Some(false), _) | (None, None) => None,
+        (_, Some(dir)) => Some(PathBuf::from(dir)),
+        (Some(true), None) => default_dir(),
+    }
+}
+
+/// A temp file in `path`'s directory; `persist` it there once it is complete
+/// so readers never see a partial entry.
+pub fn staging(path: &Path) -> Result<TempPath> {
+    let dir = path.parent().unwrap_or(Path::new("."));
+    fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let suffix = path
+        .extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default();
+    Ok(tempfile::Builder::new().suffix(&suffix).tempfile_in(dir)?.into_temp_path())
+}
+
+/// Audio extracted from `input` with the given ffmpeg filter chain.
+pub fn audio_key(input: &Path, filter: &str) -> Result<String> {
+    let input = Fingerprint::of(input, None)?;
+    Ok(format!("{:x}", Sha256::digest(format!("audio\n{}\n{filter}", input.sha256()))))
+}
+
+/// The transcript of the audio stored under `audio_key` with `config`'s
+/// models and decode settings.
+pub fn transcript_key(audio_key: &str, config: &TranscribeConfig) -> Result<String> {
+    let model = Fingerprint::of(Path::new(&config.model_path), None)?;
+    let vad_model = Fingerprint::of(Path::new(&config.vad_model_path), None)?;
+    let settings = json!({
+        "language": config.language,
+        "translate": config.translate,
+        "beam_size": config.beam_size,
+        "best_of": config.best_of,
+        "vad_threshold": config.vad_threshold,
+        "vad_min_speech_ms": config.vad_min_speech_ms,
+        "vad_min_sil_ms": config.vad_min_sil_ms,
+        "vad_pad_ms": config.vad_pad_ms,
+        "no_speech_thold": config.no_speech_thold,
+        "max_context": config.max_context,
+        "flash_attn": config.flash_attn
+    });
+    let key = format!("transcript\n{audio_key}\n{}\n{}\n{settings}", model.sha256(), vad_model.sha256());
+    Ok(format!("{:x}", Sha256::digest(key)))
+}
+
+/// `AER_CACHE_DIR`, else the per-user cache directory. Test builds never
+/// touch it unless a test passes `cache_dir`.
+#[cfg(not(test))]
+pub fn default_dir() -> Option<PathBuf> {
+    user_dir()
+}
+
+#[cfg(test)]
+pub fn default_dir() -> Option<PathBuf> {
+    None
+}
+
+fn user_dir() -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os("AER_CACHE_DIR").filter(|dir| !dir.is_empty()) {
+        return Some(PathBuf::from(dir));
+    }
+    if cfg!(windows) {
+        return std::env::var_os("LOCALAPPDATA").map(|dir| PathBuf::from(dir).join("Subtly/cache"));
+    }
+    if cfg!(target_os = "macos") {
+        return std::env::var_os("HOME").map(|home| PathBuf::from(home).join("Library/Caches/Subtly"));
+    }
+    if let Some(dir) = std::env::var_os("XDG_CACHE_HOME").filter(|dir| !dir.is_empty()) {
+        return Some(PathBuf::from(dir).join("subtly"));
+    }
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache/subtly"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{restore_env_var, ENV_LOCK};
+    use crate::transcribe_config;
+    use std::sync::PoisonError;
+
+    #[test]
+    fn keys_follow_content_and_decode_settings() {
+        let temp = tempfile::tempdir().unwrap();
+        let input = temp.path().join("a.mp4");
+        let copy = temp.path().join("b.mp4");
+        let model = temp.path().join("model.bin");
+        fs::write(&input, "video").unwrap();
+        fs::write(&copy, "video").unwrap();
+        fs::write(&model, "weights").unwrap();
+
+        let audio = audio_key(&input, "loudnorm").unwrap();
+        assert_eq!(audio, audio_key(&copy, "loudnorm").unwrap());
+        assert_ne!(audio, audio_key(&input, "anull").unwrap());
+        assert!(audio_key(&temp.path().join("missing.mp4"), "loudnorm").is_err());
+
+        let config = transcribe_config(&json!({
+            "input_path": input.to_string_lossy(),
+            "model_path": model.to_string_lossy(),
+            "vad_model_path": model.to_string_lossy()
+        }))
+        .unwrap();
+        let key = transcript_key(&audio, &config).unwrap();
+        // Output settings are applied after decoding and share the transcript.
+        let reformatted = TranscribeConfig {
+            max_len_chars: 10,
+            dedup_merge_gap_sec: 2.0,
+            output_formats: vec!["vtt".to_string()],
+            ..config.clone()
+        };
+        assert_eq!(transcript_key(&audio, &reformatted).unwrap(), key);
+        let translated = TranscribeConfig { translate: false, ..config.clone() };
+        assert_ne!(transcript_key(&audio, &translated).unwrap(), key);
+        let missing = TranscribeConfig {
+            vad_model_path: temp.path().join("vad.bin").display().to_string(),
+            ..config
+        };
+        assert!(transcript_key(&audio, &missing).is_err());
+    }
+
+    #[test]
+    fn stages_entries_next_to_their_final_path() {
+        let temp = tempfile::tempdir().unwrap();
+        let cache = Cache::new(temp.path().join("cache"));
+        let audio = cache.audio("abc");
+        assert_eq!(audio, temp.path().join("cache/audio/abc.wav"));
+        assert_eq!(cache.transcript("abc"), temp.path().join("cache/transcripts/abc.json"));
+
+        let staged = staging(&audio).unwrap();
+        assert_eq!(staged.parent(), audio.parent());
+        assert!(staged.to_string_lossy().ends_with(".wav"));
+        fs::write(&staged, "pcm").unwrap();
+        staged.persist(&audio).unwrap();
+        assert_eq!(fs::read_to_string(&audio).unwrap(), "pcm");
+
+        let bare = staging(&temp.path().join("cache/entry")).unwrap();
+        assert!(bare.extension().is_none());
+        fs::write(temp.path().join("file"), "x").unwrap();
+        let err = staging(&temp.path().join("file/sub/a.wav")).unwrap_err();
+        assert!(err.to_string().starts_with("Failed to create"), "{err}");
+    }
+
+    #[test]
+    fn caches_only_when_asked() {
+        assert_eq!(resolve_dir(None, None), None);
+        assert_eq!(resolve_dir(Some(false), Some("/cache")), None);
+        assert_eq!(resolve_dir(None, Some("/cache")), Some(PathBuf::from("/cache")));
+        assert_eq!(resolve_dir(Some(true), Some("/cache")), Some(PathBuf::from("/cache")));
+        // Test builds have no default directory.
+        assert_eq!(resolve_dir(Some(true), None), None);
+    }
+
+    #[test]
+    fn clears_only_cache_entries() {
+        let temp = tempfile::tempdir().unwrap();
+        let cache = Cache::new(temp.path().to_path_buf());
+        assert_eq!(cache.clear().unwrap(), (0, 0));
+        for (path, contents) in [(cache.audio("a"), "pcm"), (cache.transcript("a"), "{}")] {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        fs::write(temp.path().join("notes.txt"), "mine").unwrap();
+
+        assert_eq!(cache.clear().unwrap(), (2, 5));
+        assert!(!temp.path().join("audio").exists() && !temp.path().join("transcripts").exists());
+        assert!(temp.path().join("notes.txt").is_file());
+    }
+
+    #[test]
+    fn resolves_cache_dir() {
+        let _guard = ENV_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+        let saved = ["AER_CACHE_DIR", "XDG_CACHE_HOME", "HOME"].map(|name| (name, std::env::var(name).ok()));
+        assert_eq!(default_dir(), None);
+
+        std::env::set_var("AER_CACHE_DIR", "/cache");
+        assert_eq!(user_dir(), Some(PathBuf::from("/cache")));
+        std::env::set_var("AER_CACHE_DIR", "");
+        std::env::set_var("HOME", "/home/me");
+        if cfg!(all(unix, not(target_os = "macos"))) {
+            std::env::set_var("XDG_CACHE_HOME", "/xdg");
+            assert_eq!(user_dir(), Some(PathBuf::from("/xdg/subtly")));
+            std::env::remove_var("XDG_CACHE_HOME");
+            assert_eq!(user_dir(), Some(PathBuf::from("/home/me/.cache/subtly")));
+            std::env::remove_var("HOME");
+            assert_eq!(user_dir(), None);
+        }
+
+        for (name, value) in saved {
+            restore_env_var(name, value);
+        }
+    }
+}
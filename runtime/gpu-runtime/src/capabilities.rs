@@ -0,0 +1,223 @@
+//! What this runtime build supports, reported by `initialize` so the app can
+//! detect a mismatched runtime before it sends real work.
+
+use std::io::Read;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Bumped whenever requests, results or events change incompatibly.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Every method `handle_request` dispatches.
+pub const METHODS: &[&str] = &[
+    "initialize",
+    "capabilities",
+    "ping",
+    "list_devices",
+    "smoke_test",
+    "transcribe",
+    "job_submit",
+    "job_status",
+    "job_list",
+    "list_presets",
+    "lint_subtitles",
+    "fix_subtitles",
+    "retime_subtitles",
+    "sync_subtitles",
+    "cache_clear",
+    "job_resume",
+    "cancel",
+    "shutdown",
+    "authenticate",
+];
+
+/// Output formats `transcribe` accepts.
+pub const OUTPUT_FORMATS: &[&str] = &["srt", "vtt", "json", "csv", "txt", "words"];
+
+/// Parameters `transcribe` understands.
+pub const TRANSCRIBE_PARAMS: &[&str] = &[
+    "input_path",
+    "output_dir",
+    "model_path",
+    "vad_model_path",
+    "whisper_path",
+    "vad_path",
+    "ffmpeg_path",
+    "vk_icd_filenames",
+    "threads",
+    "beam_size",
+    "best_of",
+    "max_len_chars",
+    "split_on_word",
+    "vad_threshold",
+    "vad_min_speech_ms",
+    "vad_min_sil_ms",
+    "vad_pad_ms",
+    "no_speech_thold",
+    "max_context",
+    "dedup_merge_gap_sec",
+    "translate",
+    "language",
+    "flash_attn",
+    "output_formats",
+    "dry_run",
+    "force",
+    "review",
+    "review_threshold",
+    "review_speech",
+    "review_speech_threshold",
+    "hallucination_filters",
+    "hallucination_phrases",
+    "reflow",
+    "max_line_chars",
+    "max_lines",
+    "max_cps",
+    "min_duration_ms",
+    "max_duration_ms",
+    "min_gap_ms",
+    "fix",
+    "close_gap_ms",
+    "cache",
+    "cache_dir",
+    "extract_concurrency",
+    "whisper_concurrency",
+    "gpu_devices",
+    "preset",
+];
+
+/// How long a tool may take to print its version before it is killed.
+pub const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+const PROBE_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Runs `command` (e.g. `ffmpeg -version`) and returns the version from the
+/// first line it prints, or `None` if it fails, hangs or prints nothing.
+pub fn probe_version(mut command: Command, timeout: Duration) -> Option<String> {
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    let mut child = command.spawn().ok()?;
+    let stdout = read_all(child.stdout.take());
+    let stderr = read_all(child.stderr.take());
+
+    let started = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait().ok()? {
+            break status;
+        }
+        if started.elapsed() >= timeout {
+            let _ = child.kill();
+            let _ = child.wait();
+            return None;
+        }
+        thread::sleep(PROBE_POLL_INTERVAL);
+    };
+    if !status.success() {
+        return None;
+    }
+    // Some tools print their version to stderr; prefer stdout when both exist.
+    [stdout, stderr]
+        .into_iter()
+        .filter_map(|reader| reader.join().ok())
+        .map(|output| String::from_utf8_lossy(&output).into_owned())
+        .find_map(|output| output.lines().find(|line| !line.trim().is_empty()).and_then(parse_version_line))
+}
+
+fn read_all(pipe: Option<impl Read + Send + 'static>) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut output = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut output);
+        }
+        output
+    })
+}
+
+/// Extracts `6.1.1` from `ffmpeg version 6.1.1 Copyright ...` or a bare
+/// `v1.2.3`; `None` when no version number follows, e.g. in an error message.
+pub fn parse_version_line(line: &str) -> Option<String> {
+    let line = line.trim();
+    let rest = find_ignore_ascii_case(line, "version").map_or(line, |index| &line[index + "version".len()..]);
+    let token = rest.trim_start_matches([':', ' ', '\t']).split_whitespace().next()?;
+    let number = token.strip_prefix(['v', 'V']).unwrap_or(token);
+    number.starts_with(|c: char| c.is_ascii_digit()).then(|| token.to_string())
+}
+
+/// Byte index of the ASCII `needle` in `haystack`, ignoring ASCII case. Unlike
+/// searching a lowercased copy, the index is always valid in `haystack`.
+fn find_ignore_ascii_case(haystack: &str, needle: &str) -> Option<usize> {
+    haystack
+        .as_bytes()
+        .windows(needle.len())
+        .position(|window| window.eq_ignore_ascii_case(needle.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_version_lines() {
+        let version = |line| parse_version_line(line);
+        assert_eq!(
+            version("ffmpeg version 6.1.1-3ubuntu5 Copyright (c) 2000-2023").as_deref(),
+            Some("6.1.1-3ubuntu5")
+        );
+        assert_eq!(version("whisper.cpp Version: 1.7.4").as_deref(), Some("1.7.4"));
+        assert_eq!(version("  v1.2.3  ").as_deref(), Some("v1.2.3"));
+        assert_eq!(version("no version"), None);
+        assert_eq!(version("error: unknown argument: --version"), None);
+        // Lowercasing "İ" takes an extra byte; the match must not shift.
+        assert_eq!(version("İİ version 2.0").as_deref(), Some("2.0"));
+    }
+
+    #[test]
+    fn lists_every_output_format_once() {
+        let mut names = OUTPUT_FORMATS.to_vec();
+        names.sort_unstable();
+        names.dedup();
+        assert_eq!(names.len(), OUTPUT_FORMATS.len());
+        assert!(METHODS.contains(&"initialize"));
+        assert!(TRANSCRIBE_PARAMS.contains(&"input_path"));
+    }
+
+    #[cfg(unix)]
+    fn script(body: &str) -> (tempfile::TempDir, Command) {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tool");
+        std::fs::write(&path, format!("#!/bin/sh\n{body}")).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        (dir, Command::new(path))
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn probes_tool_versions() {
+        let (_dir, command) = script("echo\necho 'ffmpeg version 7.0 Copyright'\n");
+        assert_eq!(probe_version(command, PROBE_TIMEOUT), Some("7.0".to_string()));
+
+        let (_dir, command) = script("echo 'whisper version 1.7.4' >&2\n");
+        assert_eq!(probe_version(command, PROBE_TIMEOUT), Some("1.7.4".to_string()));
+
+        let (_dir, command) = script("echo 'version 1'\nexit 3\n");
+        assert_eq!(probe_version(command, PROBE_TIMEOUT), None);
+
+        let (_dir, command) = script("exit 0\n");
+        assert_eq!(probe_version(command, PROBE_TIMEOUT), None);
+
+        let (_dir, command) = script("sleep 5\n");
+        let started = Instant::now();
+        assert_eq!(probe_version(command, Duration::from_millis(100)), None);
+        assert!(started.elapsed() < Duration::from_secs(4));
+    }
+
+    #[test]
+    fn probe_reports_missing_tools() {
+        let command = Command::new("/nonexistent/definitely-missing-tool");
+        assert_eq!(probe_version(command, PROBE_TIMEOUT), None);
+    }
+}
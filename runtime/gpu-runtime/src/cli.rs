@@ -0,0 +1,548 @@
+//! Headless subcommands for shells, cron and render nodes without the desktop
+//! app. They run the same code paths as the JSON-RPC methods and print
+//! human-readable progress instead of protocol messages.
+
+use crate::capabilities::TRANSCRIBE_PARAMS;
+use crate::errors::{self, RuntimeError};
+use crate::listener::{self, ListenOptions};
+use crate::{list_devices, run_transcribe, smoke_test, transcribe_config, Runtime};
+use anyhow::{anyhow, bail, Result};
+use serde_json::json;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicI32, Ordering};
+
+pub const EXIT_SUCCESS: i32 = 0;
+/// The command ran but failed, e.g. a missing model or a whisper-cli crash.
+pub const EXIT_FAILURE: i32 = 1;
+/// The command line or the params it maps to are invalid.
+pub const EXIT_USAGE: i32 = 2;
+
+pub const USAGE: &str = "\
+Usage:
+  gpu-runtime                                      Serve JSON-RPC on stdin/stdout
+  gpu-runtime --listen <endpoint> [--token <token>]
+                                                   Serve JSON-RPC on a socket
+  gpu-runtime transcribe <path> [options]          Transcribe a file or folder
+  gpu-runtime devices                              List GPU adapters
+  gpu-runtime smoke-test                           Create a device on the first GPU
+  gpu-runtime help                                 Show this help
+
+Transcribe options mirror the transcribe params with dashes, e.g.
+  --format srt,vtt --language de --output-dir subs --model-path model.bin
+  --gpu-devices 0,1 --whisper-concurrency 2 --dry-run
+Boolean options (--translate, --split-on-word, --flash-attn, --dry-run) can be
+turned off with --no-<option>.";
+
+/// Transcribe params that take no value on the command line.
+const FLAG_PARAMS: &[&str] = &["split_on_word", "translate", "flash_attn", "dry_run", "force", "review", "reflow", "fix", "cache"];
+
+/// Transcribe params passed through as strings; everything else not listed
+/// here or in [`FLAG_PARAMS`] is a number or a comma-separated list.
+const STRING_PARAMS: &[&str] = &[
+    "input_path",
+    "output_dir",
+    "model_path",
+    "vad_model_path",
+    "whisper_path",
+    "vad_path",
+    "ffmpeg_path",
+    "vk_icd_filenames",
+    "language",
+    "review_speech",
+    "preset",
+    "cache_dir",
+];
+
+/// What the binary was asked to do.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Invocation {
+    /// Speak JSON-RPC, on stdio or on a socket.
+    Serve(Option<ListenOptions>),
+    Headless(Command),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Transcribe(serde_json::Value),
+    Devices,
+    SmokeTest,
+    Help,
+}
+
+/// Parses the arguments after the program name. `env_token` is the listener
+/// token from the environment.
+pub fn parse(args: Vec<String>, env_token: Option<String>) -> Result<Invocation> {
+    let mut args = args.into_iter();
+    let Some(first) = args.next() else {
+        return Ok(Invocation::Serve(None));
+    };
+    let command = match first.as_str() {
+        "transcribe" => Command::Transcribe(transcribe_params(args)?),
+        "devices" | "smoke-test" | "help" | "--help" | "-h" => {
+            if let Some(arg) = args.next() {
+                bail!("Unexpected argument: {arg}");
+            }
+            match first.as_str() {
+                "devices" => Command::Devices,
+                "smoke-test" => Command::SmokeTest,
+                _ => Command::Help,
+            }
+        }
+        _ => {
+            let args = std::iter::once(first).chain(args);
+            return Ok(Invocation::Serve(listener::listen_options(args, env_token)?));
+        }
+    };
+    Ok(Invocation::Headless(command))
+}
+
+/// Maps `<path> --language de --format srt,vtt ...` onto transcribe params.
+fn transcribe_params(args: impl IntoIterator<Item = String>) -> Result<serde_json::Value> {
+    let mut params = serde_json::Map::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let Some(option) = arg.strip_prefix("--") else {
+            if params.contains_key("input_path") {
+                bail!("Unexpected argument: {arg}");
+            }
+            params.insert("input_path".to_string(), json!(arg));
+            continue;
+        };
+        let (option, negated) = match option.strip_prefix("no-") {
+            Some(option) => (option, true),
+            None => (option, false),
+        };
+        let name = match option {
+            "format" | "formats" => "output_formats".to_string(),
+            option => option.replace('-', "_"),
+        };
+        if !TRANSCRIBE_PARAMS.contains(&name.as_str()) || (negated && !FLAG_PARAMS.contains(&name.as_str())) {
+            bail!("Unknown option: {arg}");
+        }
+        let value = if FLAG_PARAMS.contains(&name.as_str()) {
+            json!(!negated)
+        } else {
+            let value = args.next().ok_or_else(|| anyhow!("{arg} needs a value"))?;
+            param_value(&name, &value).map_err(|err| anyhow!("{arg}: {err}"))?
+        };
+        params.insert(name, value);
+    }
+    if !params.contains_key("input_path") {
+        bail!("transcribe needs an input file or folder");
+    }
+    Ok(serde_json::Value::Object(params))
+}
+
+fn param_value(name: &str, value: &str) -> Result<serde_json::Value> {
+    let list = || value.split(',').map(str::trim).filter(|item| !item.is_empty());
+    Ok(match name {
+        "output_formats" | "hallucination_filters" | "hallucination_phrases" => json!(list().collect::<Vec<_>>()),
+        "gpu_devices" => json!(list()
+            .map(|device| device.parse::<u32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| anyhow!("expected device indices like 0,1, got {value}"))?),
+        name if STRING_PARAMS.contains(&name) => json!(value),
+        _ => serde_json::Value::Number(
+            serde_json::from_str(value).map_err(|_| anyhow!("expected a number, got {value}"))?,
+        ),
+    })
+}
+
+/// Runs a headless command and returns the process exit status. Results go
+/// to `stdout`, errors to `stderr` and job progress to `runtime.events`.
+/// `signal` holds the termination signal received meanwhile, if any.
+pub fn run(
+    command: Command,
+    runtime: &Runtime,
+    signal: &AtomicI32,
+    stdout: &mut impl Write,
+    stderr: &mut impl Write,
+) -> Result<i32> {
+    let result = match command {
+        Command::Help => {
+            writeln!(stdout, "{USAGE}")?;
+            return Ok(EXIT_SUCCESS);
+        }
+        Command::Devices => list_devices().map(|devices| describe_devices(&devices)),
+        Command::SmokeTest => smoke_test().map(|result| result["message"].as_str().unwrap_or_default().to_string()),
+        Command::Transcribe(params) => transcribe(&params, runtime),
+    };
+    match result {
+        Ok(output) => {
+            writeln!(stdout, "{output}")?;
+            Ok(EXIT_SUCCESS)
+        }
+        Err(err) if errors::is_cancelled(&err) && signal.load(Ordering::SeqCst) != 0 => {
+            writeln!(stderr, "Interrupted")?;
+            Ok(128 + signal.load(Ordering::SeqCst))
+        }
+        Err(err) => {
+            writeln!(stderr, "Error: {err}")?;
+            let invalid = matches!(err.downcast_ref(), Some(RuntimeError::InvalidParams(_)));
+            Ok(if invalid { EXIT_USAGE } else { EXIT_FAILURE })
+        }
+    }
+}
+
+/// Runs one job on the calling thread and returns the outputs it wrote, one
+/// path per line.
+fn transcribe(params: &serde_json::Value, runtime: &Runtime) -> Result<String> {
+    let config = transcribe_config(&runtime.config()?.resolve(params)?)?;
+    let job = runtime.jobs.register();
+    let result = run_transcribe(&config, &mut runtime.events.clone(), &job, &runtime.journal);
+    runtime.jobs.finish(job.id);
+    let summary = result?;
+    let outputs = summary["outputs"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|output| output.as_str())
+        .collect::<Vec<_>>();
+    Ok(outputs.join("\n"))
+}
+
+fn describe_devices(devices: &serde_json::Value) -> String {
+    let devices = devices["devices"].as_array().cloned().unwrap_or_default();
+    if devices.is_empty() {
+        return "No GPU adapters found".to_string();
+    }
+    devices
+        .iter()
+        .enumerate()
+        .map(|(index, device)| {
+            let text = |key: &str| device[key].as_str().unwrap_or_default().to_string();
+            let mut line = format!("{index}: {} ({}, {})", text("name"), text("device_type"), text("backend"));
+            let driver = [text("driver"), text("driver_info")]
+                .into_iter()
+                .filter(|part| !part.is_empty())
+                .collect::<Vec<_>>();
+            if !driver.is_empty() {
+                line.push_str(&format!(" driver {}", driver.join(" ")));
+            }
+            line
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Turns the event stream of a job into human-readable lines. Progress is
+/// shown in steps of ten percent per file and stage.
+pub struct Progress<W> {
+    out: W,
+    pending: Vec<u8>,
+    shown: HashMap<(u64, String), u64>,
+}
+
+impl<W: Write> Progress<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            pending: Vec::new(),
+            shown: HashMap::new(),
+        }
+    }
+
+    fn describe(&mut self, event: &serde_json::Value) -> Option<String> {
+        let params = &event["params"];
+        let position = || {
+            let index = params["file_index"].as_u64().unwrap_or_default();
+            let name = params["file"].as_str().map(Path::new).and_then(Path::file_name);
+            (
+                index,
+                format!(
+                    "[{}/{}] {}",
+                    index + 1,
+                    params["file_count"],
+                    name.map(|name| name.to_string_lossy()).unwrap_or_default()
+                ),
+            )
+        };
+        match event["method"].as_str()? {
+            "log" => params["message"].as_str().map(str::to_string),
+            "progress" => {
+                let (index, position) = position();
+                let stage = params["stage"].as_str().unwrap_or_default();
+                let percent = params["percent"].as_f64().unwrap_or_default();
+                let step = (percent / 10.0) as u64;
+                if self.shown.insert((index, stage.to_string()), step) == Some(step) {
+                    return None;
+                }
+                let mut line = format!("{position}: {stage} {percent:.0}%");
+                if let Some(eta) = params["eta_sec"].as_f64().filter(|_| percent < 100.0) {
+                    line.push_str(&format!(", {eta:.0}s left"));
+                }
+                Some(line)
+            }
+            "file_completed" => Some(format!("{}: done", position().1)),
+            _ => None,
+        }
+    }
+}
+
+impl<W: Write> Write for Progress<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        while let Some(end) = self.pending.iter().position(|byte| *byte == b'\n') {
+            let line = self.pending.drain(..=end).collect::<Vec<_>>();
+            let Ok(event) = serde_json::from_slice::<serde_json::Value>(&line) else {
+                continue;
+            };
+            if let Some(text) = self.describe(&event) {
+                writeln!(self.out, "{text}")?;
+            }
+        }
+        self.out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::journal::Journal;
+    use crate::listener::Endpoint;
+    use crate::SharedWriter;
+    use std::fs;
+
+    fn args(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    fn headless(values: &[&str]) -> Result<Command> {
+        match parse(args(values), None)? {
+            Invocation::Headless(command) => Ok(command),
+            other => panic!("expected a headless command, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn parses_server_invocations() {
+        assert_eq!(parse(args(&[]), None).unwrap(), Invocation::Serve(None));
+        let Invocation::Serve(Some(options)) =
+            parse(args(&["--listen", "tcp:127.0.0.1:1"]), Some("t".to_string())).unwrap()
+        else {
+            panic!("expected listener options");
+        };
+        assert_eq!(options.endpoint, Endpoint::Tcp("127.0.0.1:1".parse().unwrap()));
+        let err = parse(args(&["transcribez"]), None).unwrap_err();
+        assert_eq!(err.to_string(), "Unknown argument: transcribez");
+    }
+
+    #[test]
+    fn parses_simple_commands() {
+        assert_eq!(headless(&["devices"]).unwrap(), Command::Devices);
+        assert_eq!(headless(&["smoke-test"]).unwrap(), Command::SmokeTest);
+        assert_eq!(headless(&["help"]).unwrap(), Command::Help);
+        assert_eq!(headless(&["--help"]).unwrap(), Command::Help);
+        assert_eq!(headless(&["-h"]).unwrap(), Command::Help);
+        let err = headless(&["devices", "--all"]).unwrap_err();
+        assert_eq!(err.to_string(), "Unexpected argument: --all");
+    }
+
+    #[test]
+    fn maps_transcribe_options_onto_params() {
+        let command = headless(&[
+            "transcribe",
+            "talk.mp4",
+            "--format",
+            "srt, vtt,",
+            "--language",
+            "de",
+            "--no-translate",
+            "--dry-run",
+            "--model-path",
+            "123",
+            "--threads",
+            "4",
+            "--vad-threshold",
+            "0.5",
+            "--gpu-devices",
+            "0,1",
+        ])
+        .unwrap();
+        assert_eq!(
+            command,
+            Command::Transcribe(json!({
+                "input_path": "talk.mp4",
+                "output_formats": ["srt", "vtt"],
+                "language": "de",
+                "translate": false,
+                "dry_run": true,
+                "model_path": "123",
+                "threads": 4,
+                "vad_threshold": 0.5,
+                "gpu_devices": [0, 1]
+            }))
+        );
+        let Command::Transcribe(params) = headless(&["transcribe", "--formats", "json", "a.wav"]).unwrap() else {
+            unreachable!()
+        };
+        assert_eq!(params["output_formats"], json!(["json"]));
+    }
+
+    #[test]
+    fn rejects_bad_transcribe_options() {
+        let cases = [
+            (vec!["transcribe"], "transcribe needs an input file or folder"),
+            (vec!["transcribe", "a.mp4", "b.mp4"], "Unexpected argument: b.mp4"),
+            (vec!["transcribe", "a.mp4", "--colour", "x"], "Unknown option: --colour"),
+            (vec!["transcribe", "a.mp4", "--no-language"], "Unknown option: --no-language"),
+            (vec!["transcribe", "a.mp4", "--language"], "--language needs a value"),
+            (
+                vec!["transcribe", "a.mp4", "--threads", "many"],
+                "--threads: expected a number, got many",
+            ),
+            (
+                vec!["transcribe", "a.mp4", "--gpu-devices", "0,x"],
+                "--gpu-devices: expected device indices like 0,1, got 0,x",
+            ),
+        ];
+        for (values, message) in cases {
+            assert_eq!(headless(&values).unwrap_err().to_string(), message);
+        }
+    }
+
+    fn run_command(command: Command, runtime: &Runtime, signal: i32) -> (i32, String, String) {
+        let (mut stdout, mut stderr) = (Vec::new(), Vec::new());
+        let code = run(command, runtime, &AtomicI32::new(signal), &mut stdout, &mut stderr).unwrap();
+        (
+            code,
+            String::from_utf8(stdout).unwrap(),
+            String::from_utf8(stderr).unwrap(),
+        )
+    }
+
+    fn headless_runtime() -> (Runtime, crate::tests::SharedBuffer) {
+        let progress = crate::tests::SharedBuffer::default();
+        let runtime = Runtime::with_journal(SharedWriter::new(Progress::new(progress.clone())), Journal::in_memory());
+        (runtime, progress)
+    }
+
+    #[test]
+    fn runs_help_and_smoke_test() {
+        let (runtime, _) = headless_runtime();
+        let (code, stdout, _) = run_command(Command::Help, &runtime, 0);
+        assert_eq!(code, EXIT_SUCCESS);
+        assert!(stdout.contains("gpu-runtime transcribe <path>"));
+
+        let (code, stdout, _) = run_command(Command::SmokeTest, &runtime, 0);
+        assert_eq!(code, EXIT_SUCCESS);
+        assert_eq!(stdout, "Smoke test ok on Test Adapter (Vulkan)\n");
+
+        let (code, stdout, _) = run_command(Command::Devices, &runtime, 0);
+        assert_eq!(code, EXIT_SUCCESS);
+        assert!(!stdout.is_empty());
+    }
+
+    #[test]
+    fn describes_devices() {
+        assert_eq!(describe_devices(&json!({ "devices": [] })), "No GPU adapters found");
+        let devices = json!({ "devices": [
+            { "name": "RTX", "device_type": "DiscreteGpu", "backend": "Vulkan", "driver": "NVIDIA", "driver_info": "550.1" },
+            { "name": "llvmpipe", "device_type": "Cpu", "backend": "Gl", "driver": "", "driver_info": "" }
+        ]});
+        assert_eq!(
+            describe_devices(&devices),
+            "0: RTX (DiscreteGpu, Vulkan) driver NVIDIA 550.1\n1: llvmpipe (Cpu, Gl)"
+        );
+    }
+
+    #[test]
+    fn transcribes_and_prints_outputs() {
+        let temp = tempfile::tempdir().unwrap();
+        let media = temp.path().join("talk.mp4");
+        fs::write(&media, "x").unwrap();
+        let (runtime, progress) = headless_runtime();
+        let params = json!({ "input_path": media.to_string_lossy(), "dry_run": true, "output_formats": ["srt", "vtt"] });
+        let (code, stdout, stderr) = run_command(Command::Transcribe(params), &runtime, 0);
+        assert_eq!(code, EXIT_SUCCESS, "{stderr}");
+        let outputs = stdout.lines().collect::<Vec<_>>();
+        assert_eq!(outputs.len(), 2);
+        assert!(outputs[0].ends_with("talk.srt"));
+        assert!(outputs[1].ends_with("talk.vtt"));
+        let progress = progress.contents();
+        assert!(progress.contains("Processing"), "{progress}");
+        assert!(progress.contains("[1/1] talk.mp4: extract 100%"), "{progress}");
+        assert!(progress.contains("[1/1] talk.mp4: done"), "{progress}");
+        assert!(!progress.contains("jsonrpc"));
+    }
+
+    #[test]
+    fn transcribe_failures_set_the_exit_code() {
+        let temp = tempfile::tempdir().unwrap();
+        let (runtime, _) = headless_runtime();
+
+        let missing = json!({ "input_path": temp.path().join("missing.mp4").to_string_lossy(), "dry_run": true });
+        let (code, _, stderr) = run_command(Command::Transcribe(missing.clone()), &runtime, 0);
+        assert_eq!(code, EXIT_FAILURE);
+        assert!(stderr.starts_with("Error: Input path does not exist"), "{stderr}");
+
+        let invalid = json!({ "input_path": "a.mp4", "whisper_concurrency": 0 });
+        let (code, _, stderr) = run_command(Command::Transcribe(invalid), &runtime, 0);
+        assert_eq!(code, EXIT_USAGE);
+        assert_eq!(stderr, "Error: Concurrency limits must be at least 1\n");
+
+        let preset = json!({ "input_path": "a.mp4", "preset": "broadcast" });
+        let (code, _, stderr) = run_command(Command::Transcribe(preset), &runtime, 0);
+        assert_eq!((code, stderr.as_str()), (EXIT_USAGE, "Error: Unknown preset: broadcast\n"));
+
+        let media = temp.path().join("talk.mp4");
+        fs::write(&media, "x").unwrap();
+        let params = json!({ "input_path": media.to_string_lossy(), "dry_run": true });
+        runtime.begin_shutdown();
+        // As if SIGINT arrived while the job ran.
+        let (code, stdout, stderr) = run_command(Command::Transcribe(params.clone()), &runtime, 2);
+        assert_eq!((code, stdout.as_str(), stderr.as_str()), (130, "", "Interrupted\n"));
+        // Cancelled without a signal, e.g. by a failing sibling: a plain failure.
+        let (code, _, stderr) = run_command(Command::Transcribe(params), &runtime, 0);
+        assert_eq!(code, EXIT_FAILURE);
+        assert_eq!(stderr, "Error: Job cancelled\n");
+        runtime.jobs.wait_idle();
+    }
+
+    #[test]
+    fn progress_shows_readable_lines() {
+        let buffer = crate::tests::SharedBuffer::default();
+        let mut progress = Progress::new(buffer.clone());
+        let event = |method: &str, params: serde_json::Value| {
+            format!("{}\n", json!({ "jsonrpc": "2.0", "method": method, "params": params }))
+        };
+        let step = |percent: f64, eta: Option<f64>| {
+            event(
+                "progress",
+                json!({ "file_index": 1, "file_count": 3, "file": "/media/b.mp4", "stage": "transcribe", "percent": percent, "eta_sec": eta }),
+            )
+        };
+        let stream = [
+            event("log", json!({ "message": "Processing: /media/b.mp4" })),
+            step(0.0, None),
+            step(4.0, Some(96.0)),
+            step(12.5, Some(70.0)),
+            step(100.0, Some(0.0)),
+            event("segment", json!({ "text": "Hi" })),
+            "not json\n".to_string(),
+            event("file_completed", json!({ "file_index": 1, "file_count": 3, "file": "/media/b.mp4" })),
+        ]
+        .concat();
+        // Events may arrive split across writes; only whole lines are shown.
+        let (head, tail) = stream.split_at(20);
+        progress.write_all(head.as_bytes()).unwrap();
+        progress.flush().unwrap();
+        assert_eq!(buffer.contents(), "");
+        progress.write_all(tail.as_bytes()).unwrap();
+        progress.flush().unwrap();
+        assert_eq!(
+            buffer.contents(),
+            "Processing: /media/b.mp4\n\
+             [2/3] b.mp4: transcribe 0%\n\
+             [2/3] b.mp4: transcribe 12%, 70s left\n\
+             [2/3] b.mp4: transcribe 100%\n\
+             [2/3] b.mp4: done\n"
+        );
+    }
+}
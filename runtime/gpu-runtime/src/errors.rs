@@ -0,0 +1,277 @@
+//! Error taxonomy reported to clients as a numeric `code` plus structured `data`.
+
+use serde_json::json;
+use std::fmt;
+
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+pub const INTERNAL_ERROR: i64 = -32603;
+pub const MISSING_ASSET: i64 = 1001;
+pub const MISSING_INPUT: i64 = 1002;
+pub const SUBPROCESS_FAILED: i64 = 1003;
+pub const CANCELLED: i64 = 1004;
+pub const GPU_UNAVAILABLE: i64 = 1005;
+pub const IO_ERROR: i64 = 1006;
+pub const PROTOCOL_MISMATCH: i64 = 1007;
+pub const SHUTTING_DOWN: i64 = 1008;
+pub const UNAUTHORIZED: i64 = 1009;
+pub const INVALID_CONFIG: i64 = 1010;
+
+/// Maximum number of stderr characters carried in `SubprocessFailed` data.
+const STDERR_TAIL_CHARS: usize = 2000;
+
+#[derive(Debug)]
+pub enum RuntimeError {
+    MethodNotFound(String),
+    InvalidParams(String),
+    /// A model or tool binary the pipeline needs is missing.
+    MissingAsset { label: String, path: String },
+    /// The media the client asked for does not exist or contains nothing usable.
+    MissingInput { message: String, path: String },
+    SubprocessFailed {
+        command: String,
+        exit_code: Option<i32>,
+        stderr: String,
+        output: String,
+    },
+    Cancelled,
+    GpuUnavailable(String),
+    /// The client was built against a different protocol version.
+    ProtocolMismatch { client: u32, runtime: u32 },
+    /// `shutdown` was requested; no new jobs are accepted.
+    ShuttingDown,
+    /// A socket client did not open with a valid `authenticate` request.
+    Unauthorized,
+    /// The preset config file cannot be parsed.
+    InvalidConfig { path: String, message: String },
+}
+
+impl RuntimeError {
+    pub fn code(&self) -> i64 {
+        match self {
+            RuntimeError::MethodNotFound(_) => METHOD_NOT_FOUND,
+            RuntimeError::InvalidParams(_) => INVALID_PARAMS,
+            RuntimeError::MissingAsset { .. } => MISSING_ASSET,
+            RuntimeError::MissingInput { .. } => MISSING_INPUT,
+            RuntimeError::SubprocessFailed { .. } => SUBPROCESS_FAILED,
+            RuntimeError::Cancelled => CANCELLED,
+            RuntimeError::GpuUnavailable(_) => GPU_UNAVAILABLE,
+            RuntimeError::ProtocolMismatch { .. } => PROTOCOL_MISMATCH,
+            RuntimeError::ShuttingDown => SHUTTING_DOWN,
+            RuntimeError::Unauthorized => UNAUTHORIZED,
+            RuntimeError::InvalidConfig { .. } => INVALID_CONFIG,
+        }
+    }
+
+    pub fn data(&self) -> Option<serde_json::Value> {
+        match self {
+            RuntimeError::MethodNotFound(method) => Some(json!({ "method": method })),
+            RuntimeError::MissingAsset { label, path } => {
+                Some(json!({ "asset": label, "path": path }))
+            }
+            RuntimeError::MissingInput { path, .. } | RuntimeError::InvalidConfig { path, .. } => {
+                Some(json!({ "path": path }))
+            }
+            RuntimeError::SubprocessFailed {
+                command,
+                exit_code,
+                stderr,
+                ..
+            } => Some(json!({
+                "command": command,
+                "exit_code": exit_code,
+                "stderr_tail": crate::truncate_log(stderr.trim(), STDERR_TAIL_CHARS)
+            })),
+            RuntimeError::ProtocolMismatch { client, runtime } => Some(json!({
+                "client_protocol_version": client,
+                "runtime_protocol_version": runtime
+            })),
+            RuntimeError::InvalidParams(_)
+            | RuntimeError::Cancelled
+            | RuntimeError::GpuUnavailable(_)
+            | RuntimeError::ShuttingDown
+            | RuntimeError::Unauthorized => None,
+        }
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuntimeError::MethodNotFound(method) => write!(f, "Unknown method: {method}"),
+            RuntimeError::InvalidParams(message)
+            | RuntimeError::MissingInput { message, .. }
+            | RuntimeError::GpuUnavailable(message) => f.write_str(message),
+            RuntimeError::MissingAsset { label, path } => write!(f, "{label} not found at {path}"),
+            RuntimeError::SubprocessFailed {
+                command,
+                exit_code,
+                output,
+                ..
+            } => {
+                let exit = exit_code
+                    .map(|code| code.to_string())
+                    .unwrap_or_else(|| "terminated".to_string());
+                if output.is_empty() {
+                    write!(f, "Command failed (exit {exit}): {command}")
+                } else {
+                    write!(f, "Command failed (exit {exit}): {command} ({output})")
+                }
+            }
+            RuntimeError::Cancelled => f.write_str("Job cancelled"),
+            RuntimeError::ProtocolMismatch { client, runtime } => write!(
+                f,
+                "Protocol version mismatch: client speaks {client}, runtime speaks {runtime}"
+            ),
+            RuntimeError::ShuttingDown => f.write_str("Runtime is shutting down"),
+            RuntimeError::Unauthorized => f.write_str("Missing or invalid token"),
+            RuntimeError::InvalidConfig { path, message } => write!(f, "Invalid config {path}: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for RuntimeError {}
+
+/// Classifies any error raised while handling a request. Errors that are not a
+/// `RuntimeError` are reported as IO errors when caused by one, else internal.
+pub fn code_and_data(err: &anyhow::Error) -> (i64, Option<serde_json::Value>) {
+    if let Some(runtime_error) = err.downcast_ref::<RuntimeError>() {
+        return (runtime_error.code(), runtime_error.data());
+    }
+    let io_error = err
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<std::io::Error>());
+    match io_error {
+        Some(io_error) => (
+            IO_ERROR,
+            Some(json!({ "kind": format!("{:?}", io_error.kind()) })),
+        ),
+        None => (INTERNAL_ERROR, None),
+    }
+}
+
+pub fn is_cancelled(err: &anyhow::Error) -> bool {
+    matches!(err.downcast_ref::<RuntimeError>(), Some(RuntimeError::Cancelled))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+
+    fn subprocess(output: &str) -> RuntimeError {
+        RuntimeError::SubprocessFailed {
+            command: "ffmpeg -i in.mp4".to_string(),
+            exit_code: Some(1),
+            stderr: "  boom  ".to_string(),
+            output: output.to_string(),
+        }
+    }
+
+    #[test]
+    fn formats_messages() {
+        assert_eq!(
+            RuntimeError::MethodNotFound("nope".to_string()).to_string(),
+            "Unknown method: nope"
+        );
+        assert_eq!(
+            RuntimeError::MissingAsset {
+                label: "VAD model".to_string(),
+                path: "/m.bin".to_string()
+            }
+            .to_string(),
+            "VAD model not found at /m.bin"
+        );
+        assert_eq!(subprocess("").to_string(), "Command failed (exit 1): ffmpeg -i in.mp4");
+        assert_eq!(
+            subprocess("boom").to_string(),
+            "Command failed (exit 1): ffmpeg -i in.mp4 (boom)"
+        );
+        let killed = RuntimeError::SubprocessFailed {
+            command: "whisper-cli".to_string(),
+            exit_code: None,
+            stderr: String::new(),
+            output: String::new(),
+        };
+        assert_eq!(killed.to_string(), "Command failed (exit terminated): whisper-cli");
+        assert_eq!(RuntimeError::Cancelled.to_string(), "Job cancelled");
+        assert_eq!(RuntimeError::GpuUnavailable("no gpu".to_string()).to_string(), "no gpu");
+        assert_eq!(
+            RuntimeError::ProtocolMismatch { client: 2, runtime: 1 }.to_string(),
+            "Protocol version mismatch: client speaks 2, runtime speaks 1"
+        );
+        assert_eq!(RuntimeError::ShuttingDown.to_string(), "Runtime is shutting down");
+        assert_eq!(RuntimeError::Unauthorized.to_string(), "Missing or invalid token");
+        let config = RuntimeError::InvalidConfig {
+            path: "/c.toml".to_string(),
+            message: "expected `=`".to_string(),
+        };
+        assert_eq!(config.to_string(), "Invalid config /c.toml: expected `=`");
+    }
+
+    #[test]
+    fn assigns_codes_and_data() {
+        let cases = [
+            (RuntimeError::MethodNotFound("x".to_string()), METHOD_NOT_FOUND, Some(json!({ "method": "x" }))),
+            (RuntimeError::InvalidParams("bad".to_string()), INVALID_PARAMS, None),
+            (
+                RuntimeError::MissingAsset {
+                    label: "ffmpeg".to_string(),
+                    path: "/bin/ffmpeg".to_string(),
+                },
+                MISSING_ASSET,
+                Some(json!({ "asset": "ffmpeg", "path": "/bin/ffmpeg" })),
+            ),
+            (
+                RuntimeError::MissingInput {
+                    message: "gone".to_string(),
+                    path: "/in".to_string(),
+                },
+                MISSING_INPUT,
+                Some(json!({ "path": "/in" })),
+            ),
+            (
+                subprocess("boom"),
+                SUBPROCESS_FAILED,
+                Some(json!({ "command": "ffmpeg -i in.mp4", "exit_code": 1, "stderr_tail": "boom" })),
+            ),
+            (RuntimeError::Cancelled, CANCELLED, None),
+            (RuntimeError::GpuUnavailable("x".to_string()), GPU_UNAVAILABLE, None),
+            (
+                RuntimeError::ProtocolMismatch { client: 2, runtime: 1 },
+                PROTOCOL_MISMATCH,
+                Some(json!({ "client_protocol_version": 2, "runtime_protocol_version": 1 })),
+            ),
+            (RuntimeError::ShuttingDown, SHUTTING_DOWN, None),
+            (RuntimeError::Unauthorized, UNAUTHORIZED, None),
+            (
+                RuntimeError::InvalidConfig {
+                    path: "/c.toml".to_string(),
+                    message: "bad".to_string(),
+                },
+                INVALID_CONFIG,
+                Some(json!({ "path": "/c.toml" })),
+            ),
+        ];
+        for (error, code, data) in cases {
+            assert_eq!(error.code(), code);
+            assert_eq!(error.data(), data);
+        }
+    }
+
+    #[test]
+    fn classifies_foreign_errors() {
+        let err = anyhow::Error::new(RuntimeError::Cancelled);
+        assert_eq!(code_and_data(&err), (CANCELLED, None));
+        assert!(is_cancelled(&err));
+
+        let err = anyhow::Error::new(std::io::Error::new(std::io::ErrorKind::NotFound, "gone"))
+            .context("reading input");
+        assert_eq!(code_and_data(&err), (IO_ERROR, Some(json!({ "kind": "NotFound" }))));
+        assert!(!is_cancelled(&err));
+
+        assert_eq!(code_and_data(&anyhow!("other")), (INTERNAL_ERROR, None));
+    }
+}
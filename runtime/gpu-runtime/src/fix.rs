@@ -0,0 +1,439 @@
+//! Repairs cue timing: empty cues are dropped, cues are put in order,
+//! overlaps and zero-length cues are resolved, short cues are extended into
+//! the following gap and tiny gaps are closed so the picture does not flicker
+//! between cues. Every change is reported. Runs on decoded segments (the
+//! `fix` transcribe stage) and on existing files (`fix_subtitles`).
+
+use crate::subtitles::{Block, Cue, Document};
+use crate::transcript::{Format, Segment};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Timing rules for one run.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Settings {
+    /// Shorter cues are extended, as far as the next cue allows.
+    pub min_duration_ms: i64,
+    /// Kept between consecutive cues.
+    pub min_gap_ms: i64,
+    /// Gaps shorter than this are closed down to `min_gap_ms`.
+    pub close_gap_ms: i64,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            min_duration_ms: 1000,
+            min_gap_ms: 80,
+            close_gap_ms: 500,
+        }
+    }
+}
+
+/// Something with a time span and text that [`fix`] can repair.
+pub trait Timed {
+    fn start_ms(&self) -> i64;
+    fn end_ms(&self) -> i64;
+    fn set_end_ms(&mut self, end_ms: i64);
+    fn has_text(&self) -> bool;
+    /// Appends `next`'s text and extends to its end.
+    fn absorb(&mut self, next: Self);
+    /// Source line, for items read from a file.
+    fn line(&self) -> Option<usize> {
+        None
+    }
+}
+
+impl Timed for Segment {
+    fn start_ms(&self) -> i64 {
+        self.start_ms
+    }
+
+    fn end_ms(&self) -> i64 {
+        self.end_ms
+    }
+
+    fn set_end_ms(&mut self, end_ms: i64) {
+        self.end_ms = end_ms;
+    }
+
+    fn has_text(&self) -> bool {
+        !self.text.trim().is_empty()
+    }
+
+    fn absorb(&mut self, next: Self) {
+        if !self.text.ends_with(char::is_whitespace) && !next.text.starts_with(char::is_whitespace) {
+            self.text.push(' ');
+        }
+        self.text.push_str(&next.text);
+        self.words.extend(next.words);
+        self.end_ms = self.end_ms.max(next.end_ms);
+    }
+}
+
+impl Timed for Cue {
+    fn start_ms(&self) -> i64 {
+        self.start_ms
+    }
+
+    fn end_ms(&self) -> i64 {
+        self.end_ms
+    }
+
+    fn set_end_ms(&mut self, end_ms: i64) {
+        self.end_ms = end_ms;
+    }
+
+    fn has_text(&self) -> bool {
+        self.visible_lines().iter().any(|line| !line.trim().is_empty())
+    }
+
+    fn absorb(&mut self, next: Self) {
+        self.lines.extend(next.lines);
+        self.end_ms = self.end_ms.max(next.end_ms);
+    }
+
+    fn line(&self) -> Option<usize> {
+        Some(self.line)
+    }
+}
+
+/// One change [`fix`] made. `cue` is the 1-based position in the input.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Change {
+    /// `remove_empty`, `remove_malformed`, `sort`, `merge`, `fix_duration`,
+    /// `trim`, `extend`, `close_gap`, `renumber` or `move`.
+    pub action: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cue: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+    pub message: String,
+    /// Timing before and after, for changes that retime a cue.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub before: Option<Span>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub after: Option<Span>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Span {
+    pub start_ms: i64,
+    pub end_ms: i64,
+}
+
+impl Change {
+    fn new(action: &'static str, cue: Option<usize>, line: Option<usize>, message: String) -> Self {
+        Self {
+            action,
+            cue,
+            line,
+            message,
+            before: None,
+            after: None,
+        }
+    }
+}
+
+pub fn fix<T: Timed>(items: Vec<T>, settings: &Settings) -> (Vec<T>, Vec<Change>) {
+    let (cues, changes) = fix_positioned(items, settings);
+    (cues.into_iter().map(|(_, cue)| cue).collect(), changes)
+}
+
+/// [`fix`], keeping each cue's 1-based position in the input.
+fn fix_positioned<T: Timed>(items: Vec<T>, settings: &Settings) -> (Vec<(usize, T)>, Vec<Change>) {
+    let mut changes = Vec::new();
+    let mut cues = Vec::new();
+    for (index, item) in items.into_iter().enumerate() {
+        if item.has_text() {
+            cues.push((index + 1, item));
+        } else {
+            let message = "Removed a cue without text".to_string();
+            changes.push(Change::new("remove_empty", Some(index + 1), item.line(), message));
+        }
+    }
+    if cues.windows(2).any(|pair| pair[1].1.start_ms() < pair[0].1.start_ms()) {
+        cues.sort_by_key(|(_, cue)| cue.start_ms());
+        changes.push(Change::new("sort", None, None, "Sorted cues by start time".to_string()));
+    }
+
+    let mut index = 0;
+    while index < cues.len() {
+        let next_start = cues.get(index + 1).map(|(_, next)| next.start_ms());
+        let limit = next_start.map_or(i64::MAX, |start| start - settings.min_gap_ms);
+        if limit <= cues[index].1.start_ms() {
+            // The next cue starts (almost) together with this one: no timing
+            // can show both, so they become one cue.
+            let (next_position, next) = cues.remove(index + 1);
+            let (position, cue) = &mut cues[index];
+            changes.push(Change::new(
+                "merge",
+                Some(next_position),
+                next.line(),
+                format!("Merged into cue {position}, which starts at almost the same time"),
+            ));
+            cue.absorb(next);
+            continue;
+        }
+        let (position, cue) = &mut cues[index];
+        let (start, end) = (cue.start_ms(), cue.end_ms());
+        let duration = end - start;
+        if duration <= 0 {
+            let new_end = (start + settings.min_duration_ms).min(limit);
+            let message = format!("Lasted {duration} ms; now lasts {} ms", new_end - start);
+            changes.push(retime(cue, *position, "fix_duration", new_end, message));
+        } else if end > limit {
+            let message = format!("Overlapped the next cue by {} ms", end - limit);
+            changes.push(retime(cue, *position, "trim", limit, message));
+        } else if duration < settings.min_duration_ms && end < limit {
+            let new_end = (start + settings.min_duration_ms).min(limit);
+            let message = format!("Lasted {duration} ms; now lasts {} ms", new_end - start);
+            changes.push(retime(cue, *position, "extend", new_end, message));
+        }
+        let gap = next_start.map_or(i64::MAX, |next_start| next_start - cue.end_ms());
+        if gap > settings.min_gap_ms && gap < settings.close_gap_ms {
+            let message = format!("Closed a {gap} ms gap to the next cue down to {} ms", settings.min_gap_ms);
+            changes.push(retime(cue, *position, "close_gap", limit, message));
+        }
+        index += 1;
+    }
+    (cues, changes)
+}
+
+/// Moves `cue`'s end to `new_end` and describes the change.
+fn retime<T: Timed>(cue: &mut T, position: usize, action: &'static str, new_end: i64, message: String) -> Change {
+    let before = Span {
+        start_ms: cue.start_ms(),
+        end_ms: cue.end_ms(),
+    };
+    cue.set_end_ms(new_end);
+    Change {
+        before: Some(before),
+        after: Some(Span {
+            start_ms: before.start_ms,
+            end_ms: new_end,
+        }),
+        ..Change::new(action, Some(position), cue.line(), message)
+    }
+}
+
+/// Fixes a document's cues, drops malformed blocks and renumbers SRT cues.
+/// Blocks ahead of the first cue (the VTT header, styles) stay at the top and
+/// every other block (notes) stays in front of the cue that followed it, or
+/// the next one kept; a block that ends up among different cues because cues
+/// were sorted is reported as `move`.
+pub fn fix_document(document: &mut Document, settings: &Settings) -> Vec<Change> {
+    let mut changes = Vec::new();
+    let mut leading = Vec::new();
+    // Blocks after the first cue, with the position of the cue they precede
+    // (one past the last cue for trailing blocks).
+    let mut anchored = Vec::new();
+    let mut cues = Vec::new();
+    for block in document.blocks.drain(..) {
+        match block {
+            Block::Cue(cue) => cues.push(cue),
+            Block::Other {
+                line,
+                malformed: Some(why),
+                ..
+            } => changes.push(Change::new(
+                "remove_malformed",
+                None,
+                Some(line),
+                format!("Removed a block that is not a cue: {why}"),
+            )),
+            other if cues.is_empty() => leading.push(other),
+            other => anchored.push((cues.len() + 1, other)),
+        }
+    }
+    let (mut cues, fixed) = fix_positioned(cues, settings);
+    changes.extend(fixed);
+    if document.format == Format::Srt {
+        for (index, (_, cue)) in cues.iter_mut().enumerate() {
+            let number = (index + 1).to_string();
+            if cue.id.as_deref().map(str::trim) != Some(number.as_str()) {
+                let found = cue.id.replace(number.clone()).unwrap_or_else(|| "missing".to_string());
+                changes.push(Change::new(
+                    "renumber",
+                    None,
+                    Some(cue.line),
+                    format!("Renumbered cue {found} to {number}"),
+                ));
+            }
+        }
+    }
+
+    let mut kept = cues.iter().map(|(position, _)| *position).collect::<Vec<_>>();
+    kept.sort_unstable();
+    let placed = cues.iter().map(|(position, _)| *position).collect::<Vec<_>>();
+    let mut before_cue = vec![Vec::new(); cues.len() + 1];
+    for (anchor, block) in anchored {
+        // The first cue kept from the anchor on, or the end.
+        let index = match kept.iter().find(|&&position| position >= anchor) {
+            Some(position) => placed.iter().position(|placed| placed == position).unwrap(),
+            None => cues.len(),
+        };
+        let originally_before = kept.iter().filter(|&&position| position < anchor).count();
+        if index != originally_before || placed[..index].iter().any(|&position| position >= anchor) {
+            if let Block::Other { line, .. } = &block {
+                changes.push(Change::new(
+                    "move",
+                    None,
+                    Some(*line),
+                    "Moved a block along with the cue after it".to_string(),
+                ));
+            }
+        }
+        before_cue[index].push(block);
+    }
+    let mut before_cue = before_cue.into_iter();
+    for ((_, cue), blocks) in cues.into_iter().zip(before_cue.by_ref()) {
+        leading.extend(blocks);
+        leading.push(Block::Cue(cue));
+    }
+    leading.extend(before_cue.flatten());
+    document.blocks = leading;
+    changes
+}
+
+/// Change counts by action, for a quick overview.
+pub fn summary(changes: &[Change]) -> BTreeMap<&'static str, usize> {
+    let mut counts = BTreeMap::new();
+    for change in changes {
+        *counts.entry(change.action).or_insert(0) += 1;
+    }
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transcript::{test_segment, test_spans, Word};
+
+    fn actions(changes: &[Change]) -> Vec<(&str, Option<usize>)> {
+        changes.iter().map(|change| (change.action, change.cue)).collect()
+    }
+
+    #[test]
+    fn repairs_segment_timing() {
+        let segments = vec![
+            test_segment(0, 2500, " Overlaps."),
+            test_segment(2000, 2000, " Zero."),
+            test_segment(2040, 3000, " Same start."),
+            test_segment(5000, 5300, " Short."),
+            test_segment(6000, 8000, " Close."),
+            test_segment(8200, 9000, " "),
+            test_segment(8300, 10000, " Last."),
+        ];
+        let (fixed, changes) = fix(segments, &Settings::default());
+        assert_eq!(
+            test_spans(&fixed),
+            [(0, 1920), (2000, 3000), (5000, 5920), (6000, 8220), (8300, 10000)]
+        );
+        assert_eq!(fixed[1].text, " Zero. Same start.");
+        assert_eq!(
+            actions(&changes),
+            [
+                ("remove_empty", Some(6)),
+                ("trim", Some(1)),
+                ("merge", Some(3)),
+                ("extend", Some(4)),
+                ("close_gap", Some(5)),
+            ]
+        );
+        assert_eq!(changes[1].message, "Overlapped the next cue by 580 ms");
+        assert_eq!(changes[2].message, "Merged into cue 2, which starts at almost the same time");
+        assert_eq!(changes[3].before, Some(Span { start_ms: 5000, end_ms: 5300 }));
+        assert_eq!(changes[3].after, Some(Span { start_ms: 5000, end_ms: 5920 }));
+        assert_eq!(changes[4].message, "Closed a 300 ms gap to the next cue down to 80 ms");
+        assert_eq!(summary(&changes)["close_gap"], 1);
+    }
+
+    #[test]
+    fn gives_zero_length_cues_a_duration_and_sorts() {
+        let segments = vec![test_segment(4000, 3000, "Backwards"), test_segment(1000, 1000, "Zero"), test_segment(9000, 9500, "Tail.")];
+        let (fixed, changes) = fix(segments, &Settings::default());
+        assert_eq!(test_spans(&fixed), [(1000, 2000), (4000, 5000), (9000, 10000)]);
+        assert_eq!(
+            actions(&changes),
+            [("sort", None), ("fix_duration", Some(2)), ("fix_duration", Some(1)), ("extend", Some(3))]
+        );
+        assert_eq!(changes[2].message, "Lasted -1000 ms; now lasts 1000 ms");
+        let word = |text: &str| Word {
+            text: text.to_string(),
+            start_ms: 0,
+            end_ms: 5,
+            tokens: Vec::new(),
+        };
+        let mut merged = test_segment(0, 10, "a");
+        merged.words.push(word("a"));
+        let mut next = test_segment(0, 5, "\nb");
+        next.words.push(word("\nb"));
+        merged.absorb(next);
+        assert_eq!((merged.text.as_str(), merged.end_ms, merged.words.len()), ("a\nb", 10, 2));
+    }
+
+    #[test]
+    fn fixes_documents() {
+        let srt = "2\n00:00:00,000 --> 00:00:02,000\n<i>One</i>\n\n\
+                   garbage\n\n\
+                   00:00:01,500 --> 00:00:03,000\nTwo\n\n\
+                   9\n00:00:05,000 --> 00:00:05,000\n\n";
+        let mut document = Document::parse(srt, Format::Srt);
+        let changes = fix_document(&mut document, &Settings::default());
+        assert_eq!(
+            document.render(),
+            "1\n00:00:00,000 --> 00:00:01,420\n<i>One</i>\n\n2\n00:00:01,500 --> 00:00:03,000\nTwo\n"
+        );
+        let described = changes
+            .iter()
+            .map(|change| (change.action, change.line, change.message.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            described,
+            [
+                ("remove_malformed", Some(5), "Removed a block that is not a cue: no timing line"),
+                ("remove_empty", Some(11), "Removed a cue without text"),
+                ("trim", Some(2), "Overlapped the next cue by 580 ms"),
+                ("renumber", Some(2), "Renumbered cue 2 to 1"),
+                ("renumber", Some(7), "Renumbered cue missing to 2"),
+            ]
+        );
+        assert_eq!(
+            serde_json::to_value(&changes[0]).unwrap(),
+            serde_json::json!({
+                "action": "remove_malformed",
+                "line": 5,
+                "message": "Removed a block that is not a cue: no timing line"
+            })
+        );
+
+        let vtt = "WEBVTT\n\nintro\n00:00.000 --> 00:01.000\nA\n\nNOTE later\n\n00:00.040 --> 00:02.000\nB\n";
+        let mut document = Document::parse(vtt, Format::Vtt);
+        let changes = fix_document(&mut document, &Settings::default());
+        assert_eq!(actions(&changes), [("merge", Some(2))]);
+        assert_eq!(
+            document.render(),
+            "WEBVTT\n\nintro\n00:00:00.000 --> 00:00:02.000\nA\nB\n\nNOTE later\n"
+        );
+    }
+
+    #[test]
+    fn keeps_notes_next_to_their_cues() {
+        let vtt = "WEBVTT\n\nSTYLE\n::cue { color: yellow }\n\n00:05.000 --> 00:06.000\nLate\n\n\
+                   NOTE early\n\n00:01.000 --> 00:02.000\nEarly\n\nNOTE last\n\n00:09.000 --> 00:10.000\nLast\n\n\
+                   NOTE end\n";
+        let mut document = Document::parse(vtt, Format::Vtt);
+        let changes = fix_document(&mut document, &Settings::default());
+        assert_eq!(
+            document.render(),
+            "WEBVTT\n\nSTYLE\n::cue { color: yellow }\n\nNOTE early\n\n00:00:01.000 --> 00:00:02.000\nEarly\n\n\
+             00:00:05.000 --> 00:00:06.000\nLate\n\nNOTE last\n\n00:00:09.000 --> 00:00:10.000\nLast\n\nNOTE end\n"
+        );
+        let described = changes
+            .iter()
+            .map(|change| (change.action, change.line))
+            .collect::<Vec<_>>();
+        assert_eq!(described, [("sort", None), ("move", Some(9))]);
+    }
+}
@@ -0,0 +1,333 @@
+//! Text whisper tends to invent over silence or music: stock phrases from
+//! its training subtitles, words or lines stuck in a loop, and speech where
+//! the audio has none. [`filter`] removes it after decoding; [`suspect`]
+//! only names the reason, for review reports.
+
+use crate::audio::SpeechMap;
+use crate::errors::RuntimeError;
+use crate::transcript::Segment;
+use anyhow::Result;
+
+/// Lowercased stock phrases per language, as whisper writes them.
+const PHRASES: &[(&str, &[&str])] = &[
+    (
+        "en",
+        &[
+            "thanks for watching",
+            "thank you for watching",
+            "please subscribe",
+            "like and subscribe",
+            "subtitles by",
+            "subtitled by",
+            "transcribed by",
+            "captions by",
+            "amara.org",
+        ],
+    ),
+    (
+        "de",
+        &[
+            "untertitel im auftrag des zdf",
+            "untertitel von",
+            "untertitelung des zdf",
+            "vielen dank fürs zuschauen",
+            "danke fürs zuschauen",
+            "amara.org",
+        ],
+    ),
+    (
+        "es",
+        &[
+            "gracias por ver",
+            "subtítulos realizados por",
+            "subtítulos por",
+            "suscríbete",
+            "amara.org",
+        ],
+    ),
+    (
+        "fr",
+        &[
+            "merci d'avoir regardé",
+            "sous-titres réalisés par",
+            "sous-titrage",
+            "abonnez-vous",
+            "amara.org",
+        ],
+    ),
+    (
+        "it",
+        &["grazie per la visione", "sottotitoli creati dalla", "iscriviti al canale", "amara.org"],
+    ),
+    (
+        "pt",
+        &["obrigado por assistir", "legendas pela comunidade", "inscreva-se", "amara.org"],
+    ),
+    ("nl", &["bedankt voor het kijken", "ondertiteld door", "amara.org"]),
+    (
+        "ru",
+        &["спасибо за просмотр", "субтитры сделал", "редактор субтитров", "подписывайтесь на канал"],
+    ),
+    ("ja", &["ご視聴ありがとうございました", "チャンネル登録"]),
+    ("zh", &["感谢观看", "请不吝点赞", "字幕由amara.org社区提供"]),
+];
+
+/// A segment is a stock phrase when it starts with one and has at most this
+/// many other words, e.g. the name in "Subtitles by ...". Dialogue that merely
+/// contains one is kept.
+const MAX_OTHER_WORDS: usize = 2;
+
+/// A word or short phrase repeated this many times in a row is a loop.
+const LOOP_REPEATS: usize = 4;
+
+/// A line that makes [`LINE_REPEATS`] copies within [`LINE_WINDOW`] kept
+/// lines is a loop, even with other lines between. Lower counts would catch
+/// ordinary exchanges ("Yes." ... "Yes.").
+const LINE_REPEATS: usize = 4;
+const LINE_WINDOW: usize = 8;
+
+/// Segments with speech in less than this share of their audio frames are
+/// treated as silent.
+const SILENT_RATIO: f64 = 0.1;
+
+/// The checks [`filter`] can run, by their `hallucination_filters` name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Check {
+    Phrases,
+    Loops,
+    Silence,
+}
+
+pub const CHECKS: &[&str] = &["phrases", "loops", "silence"];
+
+/// The text checks can drop real dialogue, so they are opt-in.
+pub const DEFAULT_CHECKS: &[&str] = &["silence"];
+
+impl Check {
+    pub fn parse(name: &str) -> Result<Self> {
+        Ok(match name {
+            "phrases" => Self::Phrases,
+            "loops" => Self::Loops,
+            "silence" => Self::Silence,
+            _ => {
+                return Err(RuntimeError::InvalidParams(format!(
+                    "Unknown hallucination filter: {name} (expected one of {})",
+                    CHECKS.join(", ")
+                ))
+                .into())
+            }
+        })
+    }
+}
+
+/// What to look for in one file's segments.
+pub struct Filter<'a> {
+    pub checks: Vec<Check>,
+    /// Whose phrase list applies; `None` checks every list.
+    pub language: Option<&'a str>,
+    /// Phrases to remove on top of the built-in ones.
+    pub extra_phrases: &'a [String],
+    /// Needed for [`Check::Silence`]; without it that check is skipped.
+    pub speech: Option<&'a SpeechMap>,
+}
+
+/// A segment [`filter`] dropped and why: `phrase`, `loop` or `silence`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Removal {
+    pub segment: Segment,
+    pub reason: &'static str,
+}
+
+pub fn filter(segments: Vec<Segment>, filter: &Filter) -> (Vec<Segment>, Vec<Removal>) {
+    let extra_phrases = filter.extra_phrases.iter().map(|phrase| normalize(phrase)).collect::<Vec<_>>();
+    let mut phrases = stock_phrases(filter.language);
+    phrases.extend(extra_phrases.iter().map(String::as_str));
+    let mut kept: Vec<Segment> = Vec::new();
+    let mut removed = Vec::new();
+    for segment in segments {
+        let text = normalize(&segment.text);
+        let reason = if text.is_empty() {
+            None
+        } else if filter.checks.contains(&Check::Phrases) && is_stock_phrase(&text, &phrases) {
+            Some("phrase")
+        } else if filter.checks.contains(&Check::Loops)
+            && (has_loop(&text)
+                || kept
+                    .iter()
+                    .rev()
+                    .take(LINE_WINDOW)
+                    .filter(|previous| normalize(&previous.text) == text)
+                    .count()
+                    >= LINE_REPEATS - 1)
+        {
+            Some("loop")
+        } else if filter.checks.contains(&Check::Silence)
+            && filter
+                .speech
+                .is_some_and(|speech| speech.speech_ratio(segment.start_ms, segment.end_ms) < SILENT_RATIO)
+        {
+            Some("silence")
+        } else {
+            None
+        };
+        match reason {
+            Some(reason) => removed.push(Removal { segment, reason }),
+            None => kept.push(segment),
+        }
+    }
+    (kept, removed)
+}
+
+/// Why `text` looks invented, if it does: `phrase` or `loop`.
+pub fn suspect(text: &str, language: Option<&str>) -> Option<&'static str> {
+    let text = normalize(text);
+    if is_stock_phrase(&text, &stock_phrases(language)) {
+        Some("phrase")
+    } else if has_loop(&text) {
+        Some("loop")
+    } else {
+        None
+    }
+}
+
+fn normalize(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+fn stock_phrases(language: Option<&str>) -> Vec<&'static str> {
+    PHRASES
+        .iter()
+        .filter(|(code, _)| language.is_none_or(|language| language == *code))
+        .flat_map(|(_, phrases)| phrases.iter().copied())
+        .collect()
+}
+
+/// Whether normalized `text` is one of `phrases`, give or take punctuation
+/// and [`MAX_OTHER_WORDS`] words.
+fn is_stock_phrase(text: &str, phrases: &[&str]) -> bool {
+    let text = text.trim_start_matches(|c: char| !c.is_alphanumeric());
+    if !phrases.iter().any(|phrase| !phrase.is_empty() && text.starts_with(phrase)) {
+        return false;
+    }
+    let rest = phrases
+        .iter()
+        .filter(|phrase| !phrase.is_empty())
+        .fold(text.to_string(), |rest, phrase| rest.replace(phrase, " "));
+    rest.split_whitespace()
+        .filter(|word| word.chars().any(char::is_alphanumeric))
+        .count()
+        <= MAX_OTHER_WORDS
+}
+
+fn has_loop(text: &str) -> bool {
+    let words = text
+        .split_whitespace()
+        .map(|word| word.trim_matches(|c: char| !c.is_alphanumeric()))
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>();
+    (1..=3).any(|len| {
+        (0..words.len().saturating_sub(len * LOOP_REPEATS - 1)).any(|start| {
+            (1..LOOP_REPEATS)
+                .all(|repeat| words[start..start + len] == words[start + repeat * len..start + (repeat + 1) * len])
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::test_wav;
+    use crate::transcript::{test_segment, test_texts};
+
+    #[test]
+    fn spots_stock_phrases_and_loops() {
+        assert_eq!(suspect(" Thanks for watching!", None), Some("phrase"));
+        assert_eq!(suspect("Subtitles by the Amara.org community", Some("en")), Some("phrase"));
+        assert_eq!(suspect("Untertitel im Auftrag des ZDF, 2021", Some("de")), Some("phrase"));
+        assert_eq!(suspect("Untertitel im Auftrag des ZDF, 2021", Some("en")), None);
+        assert_eq!(suspect("Untertitel im Auftrag des ZDF, 2021", Some("xx")), None);
+        assert_eq!(suspect("no, no, no, no", None), Some("loop"));
+        assert_eq!(suspect("I know I know I know I know you", None), Some("loop"));
+        assert_eq!(suspect("no no no", None), None);
+        assert_eq!(suspect("We watched it, thanks.", None), None);
+        assert_eq!(suspect("Please subscribe to the newsletter, it's free.", Some("en")), None);
+        assert_eq!(suspect("Just hit the button and please subscribe", Some("en")), None);
+        assert_eq!(suspect("C'est le sous-titrage de mon film.", Some("fr")), None);
+        assert_eq!(suspect("Sous-titrage ST' 501", Some("fr")), Some("phrase"));
+        assert_eq!(suspect("字幕由Amara.org社区提供", Some("zh")), Some("phrase"));
+        assert_eq!(suspect("", None), None);
+    }
+
+    #[test]
+    fn removes_phrases_loops_and_silence() {
+        let speech = SpeechMap::read(&test_wav(&[(6000, 8000), (600, 0)])[..]).unwrap();
+        let extra = vec!["  Brought To You".to_string()];
+        let filter = Filter {
+            checks: vec![Check::Phrases, Check::Loops, Check::Silence],
+            language: Some("en"),
+            extra_phrases: &extra,
+            speech: Some(&speech),
+        };
+        let segments = vec![
+            test_segment(0, 500, " Hello."),
+            test_segment(500, 1000, " Thanks for watching!"),
+            test_segment(1000, 1500, " Hello."),
+            test_segment(1500, 2000, " Yes."),
+            test_segment(2000, 2500, " hello. "),
+            test_segment(2500, 3000, " Yes."),
+            test_segment(3000, 3200, " Hello."),
+            test_segment(3200, 3500, " you you you you"),
+            test_segment(3500, 3700, " Brought to you by nobody"),
+            test_segment(3700, 4000, " Brought to you by the people of the town."),
+            test_segment(4000, 4500, " "),
+            test_segment(6000, 6600, " In the silence."),
+        ];
+        let (kept, removed) = self::filter(segments.clone(), &filter);
+        assert_eq!(
+            test_texts(&kept),
+            [
+                " Hello.",
+                " Hello.",
+                " Yes.",
+                " hello. ",
+                " Yes.",
+                " Brought to you by the people of the town.",
+                " "
+            ]
+        );
+        let reasons = removed
+            .iter()
+            .map(|removal| (removal.segment.start_ms, removal.reason))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            reasons,
+            [(500, "phrase"), (3000, "loop"), (3200, "loop"), (3500, "phrase"), (6000, "silence")]
+        );
+
+        // Only the configured checks run, and silence needs the audio.
+        let loops_only = Filter {
+            checks: vec![Check::Loops],
+            speech: None,
+            ..filter
+        };
+        assert_eq!(self::filter(segments.clone(), &loops_only).1.len(), 2);
+        let silence_without_audio = Filter {
+            checks: vec![Check::Silence],
+            ..loops_only
+        };
+        assert!(self::filter(segments, &silence_without_audio).1.is_empty());
+    }
+
+    #[test]
+    fn parses_check_names() {
+        let checks = CHECKS.iter().map(|name| Check::parse(name).unwrap()).collect::<Vec<_>>();
+        assert_eq!(checks, [Check::Phrases, Check::Loops, Check::Silence]);
+        assert_eq!(Check::parse(DEFAULT_CHECKS[0]).unwrap(), Check::Silence);
+        let err = Check::parse("music").unwrap_err();
+        assert_eq!(crate::errors::code_and_data(&err).0, crate::errors::INVALID_PARAMS);
+        assert_eq!(
+            err.to_string(),
+            "Unknown hallucination filter: music (expected one of phrases, loops, silence)"
+        );
+    }
+}
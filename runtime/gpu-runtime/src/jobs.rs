@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+
+/// Cooperative cancellation flag shared between a job and the RPC loop.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// Identity and cancellation handle passed to a running job.
+#[derive(Debug, Clone, Default)]
+pub struct Job {
+    pub id: u64,
+    pub cancel: CancelToken,
+}
+
+/// Tracks in-flight background jobs by id.
+#[derive(Debug)]
+pub struct JobRegistry {
+    next_id: AtomicU64,
+    jobs: Mutex<HashMap<u64, CancelToken>>,
+    idle: Condvar,
+    closed: AtomicBool,
+}
+
+impl Default for JobRegistry {
+    fn default() -> Self {
+        Self::starting_at(1)
+    }
+}
+
+impl JobRegistry {
+    /// Registry handing out ids from `next_id`, e.g. past those in the journal.
+    pub fn starting_at(next_id: u64) -> Self {
+        Self {
+            next_id: AtomicU64::new(next_id),
+            jobs: Mutex::new(HashMap::new()),
+            idle: Condvar::new(),
+            closed: AtomicBool::new(false),
+        }
+    }
+
+    pub fn register(&self) -> Job {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let cancel = self.new_token();
+        self.lock().insert(id, cancel.clone());
+        Job { id, cancel }
+    }
+
+    /// Registers a job under an existing id, unless that job is still running.
+    pub fn register_existing(&self, id: u64) -> Option<Job> {
+        let mut jobs = self.lock();
+        if jobs.contains_key(&id) {
+            return None;
+        }
+        let cancel = self.new_token();
+        jobs.insert(id, cancel.clone());
+        Some(Job { id, cancel })
+    }
+
+    /// Cancels every running job; jobs registered afterwards start cancelled.
+    /// Returns how many jobs were running.
+    pub fn close(&self) -> usize {
+        let jobs = self.lock();
+        self.closed.store(true, Ordering::SeqCst);
+        for token in jobs.values() {
+            token.cancel();
+        }
+        jobs.len()
+    }
+
+    fn new_token(&self) -> CancelToken {
+        let token = CancelToken::default();
+        if self.closed.load(Ordering::SeqCst) {
+            token.cancel();
+        }
+        token
+    }
+
+    /// Flags the job for cancellation. Returns `false` if no such job is running.
+    pub fn cancel(&self, id: u64) -> bool {
+        match self.lock().get(&id) {
+            Some(token) => {
+                token.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn finish(&self, id: u64) {
+        let mut jobs = self.lock();
+        jobs.remove(&id);
+        if jobs.is_empty() {
+            self.idle.notify_all();
+        }
+    }
+
+    /// Blocks until every registered job has finished.
+    pub fn wait_idle(&self) {
+        let mut jobs = self.lock();
+        while !jobs.is_empty() {
+            jobs = self.idle.wait(jobs).unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<u64, CancelToken>> {
+        self.jobs.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn registers_and_cancels_jobs() {
+        let registry = JobRegistry::default();
+        let job = registry.register();
+        let (first, token) = (job.id, job.cancel);
+        let second = registry.register().id;
+        assert_ne!(first, second);
+
+        assert!(!token.is_cancelled());
+        assert!(registry.cancel(first));
+        assert!(token.is_cancelled());
+        assert!(!registry.cancel(999));
+
+        registry.finish(first);
+        assert!(!registry.cancel(first));
+        registry.finish(second);
+        registry.wait_idle();
+    }
+
+    #[test]
+    fn registers_existing_ids_once() {
+        let registry = JobRegistry::starting_at(10);
+        assert_eq!(registry.register().id, 10);
+        let job = registry.register_existing(3).unwrap();
+        assert_eq!(job.id, 3);
+        assert!(registry.register_existing(3).is_none());
+        assert!(registry.register_existing(10).is_none());
+        registry.finish(3);
+        assert!(registry.register_existing(3).is_some());
+        assert_eq!(registry.register().id, 11);
+    }
+
+    #[test]
+    fn close_cancels_running_and_later_jobs() {
+        let registry = JobRegistry::default();
+        let running = registry.register();
+        assert_eq!(registry.close(), 1);
+        assert!(running.cancel.is_cancelled());
+        assert!(registry.register().cancel.is_cancelled());
+        assert!(registry.register_existing(running.id).is_none());
+        assert!(registry.register_existing(99).unwrap().cancel.is_cancelled());
+    }
+
+    #[test]
+    fn wait_idle_blocks_until_jobs_finish() {
+        let registry = Arc::new(JobRegistry::default());
+        let id = registry.register().id;
+        let worker = {
+            let registry = registry.clone();
+            std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_millis(20));
+                registry.finish(id);
+            })
+        };
+        registry.wait_idle();
+        assert!(!registry.cancel(id));
+        worker.join().unwrap();
+    }
+}
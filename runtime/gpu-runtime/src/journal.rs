@@ -0,0 +1,554 @@
+//! Append-only record of submitted jobs, so a batch interrupted by a crash can
+//! be resumed without redoing the files it already finished.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const JOURNAL_FILE: &str = "jobs.jsonl";
+
+/// Finished jobs older than the newest this many are dropped when the journal
+/// is compacted on startup, whether they completed or not.
+const MAX_FINISHED_JOBS: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+    /// The runtime exited while the job was queued or running.
+    Interrupted,
+}
+
+impl JobStatus {
+    pub fn is_finished(self) -> bool {
+        !matches!(self, JobStatus::Queued | JobStatus::Running)
+    }
+}
+
+/// One line of the journal.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "entry", rename_all = "snake_case")]
+pub enum Entry {
+    Submitted {
+        job_id: u64,
+        params: serde_json::Value,
+        at: u64,
+    },
+    Started {
+        job_id: u64,
+        file_count: usize,
+        at: u64,
+    },
+    FileDone {
+        job_id: u64,
+        file: String,
+        outputs: Vec<String>,
+        at: u64,
+    },
+    Finished {
+        job_id: u64,
+        status: JobStatus,
+        error: Option<serde_json::Value>,
+        at: u64,
+    },
+}
+
+impl Entry {
+    fn job_id(&self) -> u64 {
+        match self {
+            Entry::Submitted { job_id, .. }
+            | Entry::Started { job_id, .. }
+            | Entry::FileDone { job_id, .. }
+            | Entry::Finished { job_id, .. } => *job_id,
+        }
+    }
+}
+
+/// Current state of a job, rebuilt by replaying its journal entries.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct JobRecord {
+    pub job_id: u64,
+    pub status: JobStatus,
+    pub params: serde_json::Value,
+    pub file_count: Option<usize>,
+    /// Outputs of every finished input file, keyed by input path.
+    pub completed: BTreeMap<String, Vec<String>>,
+    pub error: Option<serde_json::Value>,
+    pub submitted_at: u64,
+    pub updated_at: u64,
+}
+
+impl JobRecord {
+    fn apply(records: &mut BTreeMap<u64, JobRecord>, entry: &Entry) {
+        if let Entry::Submitted { job_id, params, at } = entry {
+            records.insert(
+                *job_id,
+                JobRecord {
+                    job_id: *job_id,
+                    status: JobStatus::Queued,
+                    params: params.clone(),
+                    file_count: None,
+                    completed: BTreeMap::new(),
+                    error: None,
+                    submitted_at: *at,
+                    updated_at: *at,
+                },
+            );
+            return;
+        }
+        let Some(record) = records.get_mut(&entry.job_id()) else {
+            return;
+        };
+        match entry {
+            Entry::Submitted { .. } => {}
+            Entry::Started { file_count, at, .. } => {
+                record.status = JobStatus::Running;
+                record.file_count = Some(*file_count);
+                record.error = None;
+                record.updated_at = *at;
+            }
+            Entry::FileDone { file, outputs, at, .. } => {
+                record.completed.insert(file.clone(), outputs.clone());
+                record.updated_at = *at;
+            }
+            Entry::Finished { status, error, at, .. } => {
+                record.status = *status;
+                record.error = error.clone();
+                record.updated_at = *at;
+            }
+        }
+    }
+
+    /// Entries that recreate this record on replay.
+    fn entries(&self) -> Vec<Entry> {
+        let mut entries = vec![Entry::Submitted {
+            job_id: self.job_id,
+            params: self.params.clone(),
+            at: self.submitted_at,
+        }];
+        if let Some(file_count) = self.file_count {
+            entries.push(Entry::Started {
+                job_id: self.job_id,
+                file_count,
+                at: self.updated_at,
+            });
+        }
+        entries.extend(self.completed.iter().map(|(file, outputs)| Entry::FileDone {
+            job_id: self.job_id,
+            file: file.clone(),
+            outputs: outputs.clone(),
+            at: self.updated_at,
+        }));
+        if self.status.is_finished() {
+            entries.push(Entry::Finished {
+                job_id: self.job_id,
+                status: self.status,
+                error: self.error.clone(),
+                at: self.updated_at,
+            });
+        }
+        entries
+    }
+}
+
+struct State {
+    file: Option<File>,
+    records: BTreeMap<u64, JobRecord>,
+}
+
+/// Job history, persisted as JSON lines when opened on a directory.
+pub struct Journal {
+    state: Mutex<State>,
+}
+
+impl Journal {
+    /// Journal that keeps job state for this process only.
+    pub fn in_memory() -> Self {
+        Self {
+            state: Mutex::new(State {
+                file: None,
+                records: BTreeMap::new(),
+            }),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn with_file(file: File) -> Self {
+        Self {
+            state: Mutex::new(State {
+                file: Some(file),
+                records: BTreeMap::new(),
+            }),
+        }
+    }
+
+    /// Replays `dir/jobs.jsonl`, marks jobs that were still active as
+    /// interrupted, and rewrites the file in compact form.
+    pub fn open(dir: &Path) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join(JOURNAL_FILE);
+        let mut records = BTreeMap::new();
+        if path.exists() {
+            let reader = BufReader::new(File::open(&path)?);
+            for line in reader.lines() {
+                // A crash mid-write leaves a torn last line; skip it.
+                if let Ok(entry) = serde_json::from_str::<Entry>(&line?) {
+                    JobRecord::apply(&mut records, &entry);
+                }
+            }
+        }
+        for record in records.values_mut() {
+            if !record.status.is_finished() {
+                record.status = JobStatus::Interrupted;
+            }
+        }
+        prune_finished(&mut records);
+
+        let compacted = dir.join(format!("{JOURNAL_FILE}.tmp"));
+        let mut file = File::create(&compacted)?;
+        for entry in records.values().flat_map(JobRecord::entries) {
+            write_entry(&mut file, &entry)?;
+        }
+        file.sync_all()?;
+        fs::rename(&compacted, &path)?;
+
+        let file = OpenOptions::new().append(true).open(&path)?;
+        Ok(Self {
+            state: Mutex::new(State {
+                file: Some(file),
+                records,
+            }),
+        })
+    }
+
+    /// Appends `entry` to the journal and applies it to the in-memory state.
+    pub fn record(&self, entry: Entry) -> Result<()> {
+        let mut state = self.lock();
+        if let Some(file) = state.file.as_mut() {
+            write_entry(file, &entry)?;
+        }
+        JobRecord::apply(&mut state.records, &entry);
+        Ok(())
+    }
+
+    pub fn get(&self, job_id: u64) -> Option<JobRecord> {
+        self.lock().records.get(&job_id).cloned()
+    }
+
+    /// Outputs `job_id` recorded for `file`, provided they all still exist on disk.
+    pub fn completed_outputs(&self, job_id: u64, file: &Path) -> Option<Vec<String>> {
+        let state = self.lock();
+        let outputs = state.records.get(&job_id)?.completed.get(&file.display().to_string())?;
+        outputs
+            .iter()
+            .all(|output| Path::new(output).exists())
+            .then(|| outputs.clone())
+    }
+
+    pub fn list(&self) -> Vec<JobRecord> {
+        self.lock().records.values().cloned().collect()
+    }
+
+    /// First id not used by any recorded job.
+    pub fn next_id(&self) -> u64 {
+        self.lock()
+            .records
+            .keys()
+            .next_back()
+            .map_or(1, |last| last + 1)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+fn write_entry(file: &mut File, entry: &Entry) -> Result<()> {
+    let mut line = serde_json::to_vec(entry)?;
+    line.push(b'\n');
+    file.write_all(&line)?;
+    file.sync_data()?;
+    Ok(())
+}
+
+fn prune_finished(records: &mut BTreeMap<u64, JobRecord>) {
+    let finished = records
+        .values()
+        .filter(|record| record.status.is_finished())
+        .map(|record| record.job_id)
+        .collect::<Vec<_>>();
+    let excess = finished.len().saturating_sub(MAX_FINISHED_JOBS);
+    for job_id in &finished[..excess] {
+        records.remove(job_id);
+    }
+}
+
+/// Where the journal lives: `AER_STATE_DIR`, else the per-user state directory.
+pub fn default_state_dir() -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os("AER_STATE_DIR").filter(|dir| !dir.is_empty()) {
+        return Some(PathBuf::from(dir));
+    }
+    if cfg!(windows) {
+        return std::env::var_os("LOCALAPPDATA").map(|dir| PathBuf::from(dir).join("Subtly"));
+    }
+    if cfg!(target_os = "macos") {
+        return std::env::var_os("HOME")
+            .map(|home| PathBuf::from(home).join("Library/Application Support/Subtly"));
+    }
+    if let Some(dir) = std::env::var_os("XDG_STATE_HOME").filter(|dir| !dir.is_empty()) {
+        return Some(PathBuf::from(dir).join("subtly"));
+    }
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state/subtly"))
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn submitted(job_id: u64) -> Entry {
+        Entry::Submitted {
+            job_id,
+            params: json!({ "input_path": "/media" }),
+            at: 10,
+        }
+    }
+
+    fn finished(job_id: u64, status: JobStatus) -> Entry {
+        Entry::Finished {
+            job_id,
+            status,
+            error: None,
+            at: 30,
+        }
+    }
+
+    #[test]
+    fn replays_entries_into_records() {
+        let journal = Journal::in_memory();
+        assert_eq!(journal.next_id(), 1);
+        journal.record(submitted(4)).unwrap();
+        journal
+            .record(Entry::Started {
+                job_id: 4,
+                file_count: 2,
+                at: 20,
+            })
+            .unwrap();
+        journal
+            .record(Entry::FileDone {
+                job_id: 4,
+                file: "/media/a.mp4".to_string(),
+                outputs: vec!["/media/a.srt".to_string()],
+                at: 25,
+            })
+            .unwrap();
+        // Entries for unknown jobs are ignored.
+        journal.record(finished(9, JobStatus::Completed)).unwrap();
+
+        let record = journal.get(4).unwrap();
+        assert_eq!(record.status, JobStatus::Running);
+        assert_eq!(record.file_count, Some(2));
+        assert_eq!(record.completed["/media/a.mp4"], vec!["/media/a.srt"]);
+        assert_eq!((record.submitted_at, record.updated_at), (10, 25));
+        assert_eq!(journal.next_id(), 5);
+        assert!(journal.get(9).is_none());
+
+        journal
+            .record(Entry::Finished {
+                job_id: 4,
+                status: JobStatus::Failed,
+                error: Some(json!({ "code": 1003 })),
+                at: 30,
+            })
+            .unwrap();
+        let record = journal.get(4).unwrap();
+        assert_eq!(record.status, JobStatus::Failed);
+        assert_eq!(record.error, Some(json!({ "code": 1003 })));
+        assert_eq!(journal.list().len(), 1);
+
+        // Resuming starts the job again on top of its existing record.
+        journal
+            .record(Entry::Started {
+                job_id: 4,
+                file_count: 2,
+                at: 40,
+            })
+            .unwrap();
+        let record = journal.get(4).unwrap();
+        assert_eq!(record.status, JobStatus::Running);
+        assert_eq!(record.error, None);
+    }
+
+    #[test]
+    fn reopening_marks_active_jobs_interrupted() {
+        let dir = tempfile::tempdir().unwrap();
+        {
+            let journal = Journal::open(dir.path()).unwrap();
+            journal.record(submitted(1)).unwrap();
+            journal.record(finished(1, JobStatus::Completed)).unwrap();
+            journal.record(submitted(2)).unwrap();
+            journal
+                .record(Entry::Started {
+                    job_id: 2,
+                    file_count: 3,
+                    at: 20,
+                })
+                .unwrap();
+            journal
+                .record(Entry::FileDone {
+                    job_id: 2,
+                    file: "/media/a.mp4".to_string(),
+                    outputs: vec![],
+                    at: 25,
+                })
+                .unwrap();
+            journal.record(submitted(3)).unwrap();
+        }
+        let path = dir.path().join(JOURNAL_FILE);
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"{\"entry\":\"finished\",\"job_").unwrap();
+        drop(file);
+
+        let journal = Journal::open(dir.path()).unwrap();
+        assert_eq!(journal.get(1).unwrap().status, JobStatus::Completed);
+        let record = journal.get(2).unwrap();
+        assert_eq!(record.status, JobStatus::Interrupted);
+        assert_eq!(record.file_count, Some(3));
+        assert!(record.completed.contains_key("/media/a.mp4"));
+        assert_eq!(journal.get(3).unwrap().status, JobStatus::Interrupted);
+        assert_eq!(journal.next_id(), 4);
+
+        // The rewritten journal replays to the same state.
+        let reopened = Journal::open(dir.path()).unwrap();
+        assert_eq!(reopened.list(), journal.list());
+        assert!(!dir.path().join(format!("{JOURNAL_FILE}.tmp")).exists());
+    }
+
+    #[test]
+    fn compaction_drops_old_completed_jobs() {
+        let dir = tempfile::tempdir().unwrap();
+        {
+            let journal = Journal::open(dir.path()).unwrap();
+            for job_id in 1..=(MAX_FINISHED_JOBS as u64 + 2) {
+                journal.record(submitted(job_id)).unwrap();
+                journal.record(finished(job_id, JobStatus::Completed)).unwrap();
+            }
+            journal.record(submitted(500)).unwrap();
+            journal.record(finished(500, JobStatus::Failed)).unwrap();
+            journal.record(submitted(501)).unwrap();
+        }
+        let journal = Journal::open(dir.path()).unwrap();
+        assert!(journal.get(4).is_none());
+        assert!(journal.get(5).is_some());
+        assert!(journal.get(500).is_some());
+        // The job left running was interrupted, and is the newest one kept.
+        assert_eq!(journal.get(501).unwrap().status, JobStatus::Interrupted);
+        assert_eq!(journal.list().len(), MAX_FINISHED_JOBS);
+    }
+
+    #[test]
+    fn compaction_drops_old_failed_jobs() {
+        let dir = tempfile::tempdir().unwrap();
+        let statuses = [JobStatus::Failed, JobStatus::Cancelled, JobStatus::Interrupted];
+        {
+            let journal = Journal::open(dir.path()).unwrap();
+            for job_id in 1..=(MAX_FINISHED_JOBS as u64 + 5) {
+                journal.record(submitted(job_id)).unwrap();
+                journal.record(finished(job_id, statuses[job_id as usize % 3])).unwrap();
+            }
+        }
+        let journal = Journal::open(dir.path()).unwrap();
+        assert_eq!(journal.list().len(), MAX_FINISHED_JOBS);
+        assert!(journal.get(5).is_none());
+        assert_eq!(journal.get(6).unwrap().status, JobStatus::Failed);
+    }
+
+    #[test]
+    fn open_reports_unusable_directories() {
+        let dir = tempfile::tempdir().unwrap();
+        let blocker = dir.path().join("file");
+        fs::write(&blocker, "x").unwrap();
+        assert!(Journal::open(&blocker).is_err());
+
+        fs::create_dir(dir.path().join("state")).unwrap();
+        fs::create_dir(dir.path().join("state").join(JOURNAL_FILE)).unwrap();
+        assert!(Journal::open(&dir.path().join("state")).is_err());
+    }
+
+    #[test]
+    fn checks_completed_outputs_still_exist() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("a.srt");
+        fs::write(&output, "1").unwrap();
+        let journal = Journal::in_memory();
+        journal.record(submitted(1)).unwrap();
+        for (file, outputs) in [
+            ("/media/a.mp4", vec![output.display().to_string()]),
+            ("/media/b.mp4", vec![dir.path().join("b.srt").display().to_string()]),
+        ] {
+            journal
+                .record(Entry::FileDone {
+                    job_id: 1,
+                    file: file.to_string(),
+                    outputs,
+                    at: 20,
+                })
+                .unwrap();
+        }
+        assert_eq!(
+            journal.completed_outputs(1, Path::new("/media/a.mp4")),
+            Some(vec![output.display().to_string()])
+        );
+        assert_eq!(journal.completed_outputs(1, Path::new("/media/b.mp4")), None);
+        assert_eq!(journal.completed_outputs(1, Path::new("/media/c.mp4")), None);
+        assert_eq!(journal.completed_outputs(2, Path::new("/media/a.mp4")), None);
+    }
+
+    #[test]
+    fn resolves_state_dir() {
+        let _guard = crate::tests::ENV_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+        let saved = ["AER_STATE_DIR", "XDG_STATE_HOME", "HOME"].map(|name| (name, std::env::var(name).ok()));
+
+        std::env::set_var("AER_STATE_DIR", "/state");
+        assert_eq!(default_state_dir(), Some(PathBuf::from("/state")));
+        std::env::set_var("AER_STATE_DIR", "");
+        std::env::set_var("HOME", "/home/me");
+        if cfg!(all(unix, not(target_os = "macos"))) {
+            std::env::set_var("XDG_STATE_HOME", "/xdg");
+            assert_eq!(default_state_dir(), Some(PathBuf::from("/xdg/subtly")));
+            std::env::remove_var("XDG_STATE_HOME");
+            assert_eq!(default_state_dir(), Some(PathBuf::from("/home/me/.local/state/subtly")));
+            std::env::remove_var("HOME");
+            assert_eq!(default_state_dir(), None);
+        }
+
+        for (name, value) in saved {
+            crate::tests::restore_env_var(name, value);
+        }
+        assert!(now() > 0);
+    }
+
+    #[test]
+    fn names_statuses() {
+        assert_eq!(serde_json::to_value(JobStatus::Interrupted).unwrap(), json!("interrupted"));
+        assert!(!JobStatus::Queued.is_finished());
+        assert!(!JobStatus::Running.is_finished());
+        assert!(JobStatus::Cancelled.is_finished());
+    }
+}
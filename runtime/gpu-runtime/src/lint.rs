@@ -0,0 +1,418 @@
+//! Quality checks for finished SRT/VTT files: timing, reading speed and
+//! layout against a rule profile. Built-in profiles can be tuned, and new
+//! ones added, under `[lint_profiles.<name>]` in the config file.
+
+use crate::errors::RuntimeError;
+use crate::subtitles::{Block, Document};
+use crate::transcript::Format;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+type Params = serde_json::Map<String, serde_json::Value>;
+
+/// Every check, by the name issues report it under.
+pub const RULES: &[&str] = &[
+    "malformed",
+    "empty",
+    "numbering",
+    "non_positive_duration",
+    "min_duration",
+    "max_duration",
+    "overlap",
+    "gap",
+    "cps",
+    "line_length",
+    "line_count",
+];
+
+pub const PROFILES: &[&str] = &["default", "streaming", "broadcast"];
+
+/// Limits one lint run checks against.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Profile {
+    pub max_line_chars: usize,
+    pub max_lines: usize,
+    /// Reading speed in characters per second, markup and line breaks not
+    /// counted.
+    pub max_cps: f64,
+    pub min_duration_ms: i64,
+    pub max_duration_ms: i64,
+    /// Cues closer than this many frames (but not overlapping) are flagged.
+    pub min_gap_frames: u32,
+    pub fps: f64,
+    /// The checks to run, from [`RULES`].
+    pub rules: Vec<String>,
+}
+
+impl Default for Profile {
+    /// Matches the reflow defaults, so reflowed output passes.
+    fn default() -> Self {
+        Self {
+            max_line_chars: 42,
+            max_lines: 2,
+            max_cps: 17.0,
+            min_duration_ms: 1000,
+            max_duration_ms: 7000,
+            min_gap_frames: 2,
+            fps: 25.0,
+            rules: RULES.iter().map(|rule| rule.to_string()).collect(),
+        }
+    }
+}
+
+impl Profile {
+    pub fn builtin(name: &str) -> Option<Self> {
+        let default = Self::default();
+        match name {
+            "default" => Some(default),
+            // Faster reading and the 5/6 s minimum common on streaming
+            // platforms.
+            "streaming" => Some(Self {
+                max_cps: 20.0,
+                min_duration_ms: 833,
+                fps: 24.0,
+                ..default
+            }),
+            // Shorter lines and slower reading for television.
+            "broadcast" => Some(Self {
+                max_line_chars: 37,
+                max_cps: 15.0,
+                ..default
+            }),
+            _ => None,
+        }
+    }
+
+    /// The named profile with `custom`'s entry of the same name (from the
+    /// config file) and then `overrides` layered over it. Custom names that
+    /// are not built in start from `default`.
+    pub fn resolve(name: &str, custom: &BTreeMap<String, Params>, overrides: Option<&Params>) -> Result<Self> {
+        let base = match (Self::builtin(name), custom.contains_key(name)) {
+            (Some(profile), _) => profile,
+            (None, true) => Self::default(),
+            (None, false) => {
+                let mut known = PROFILES.iter().map(|name| name.to_string()).collect::<Vec<_>>();
+                known.extend(custom.keys().filter(|name| !PROFILES.contains(&name.as_str())).cloned());
+                return Err(RuntimeError::InvalidParams(format!(
+                    "Unknown lint profile: {name} (expected one of {})",
+                    known.join(", ")
+                ))
+                .into());
+            }
+        };
+        let serde_json::Value::Object(mut merged) = serde_json::to_value(base)? else {
+            unreachable!("profiles serialize to objects");
+        };
+        merged.extend(custom.get(name).cloned().unwrap_or_default());
+        merged.extend(overrides.cloned().unwrap_or_default());
+        let profile: Self = serde_json::from_value(serde_json::Value::Object(merged))
+            .map_err(|err| RuntimeError::InvalidParams(format!("Invalid lint profile {name}: {err}")))?;
+        profile.validate()?;
+        Ok(profile)
+    }
+
+    fn validate(&self) -> Result<()> {
+        let invalid = |message: String| Err(RuntimeError::InvalidParams(message).into());
+        if self.max_line_chars == 0 || self.max_lines == 0 || self.max_cps <= 0.0 || self.fps <= 0.0 {
+            return invalid("max_line_chars, max_lines, max_cps and fps must be greater than 0".to_string());
+        }
+        if self.min_duration_ms > self.max_duration_ms {
+            return invalid("min_duration_ms must not exceed max_duration_ms".to_string());
+        }
+        if let Some(rule) = self.rules.iter().find(|rule| !RULES.contains(&rule.as_str())) {
+            return invalid(format!("Unknown lint rule: {rule} (expected one of {})", RULES.join(", ")));
+        }
+        Ok(())
+    }
+
+    pub fn min_gap_ms(&self) -> i64 {
+        (f64::from(self.min_gap_frames) * 1000.0 / self.fps).round() as i64
+    }
+
+    fn checks(&self, rule: &str) -> bool {
+        self.rules.iter().any(|enabled| enabled == rule)
+    }
+}
+
+/// One rule violation. `cue` is the 1-based position among the file's cues
+/// and `line` the 1-based line of its timing line (or of a malformed block).
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Issue {
+    pub rule: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cue: Option<usize>,
+    pub line: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_ms: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_ms: Option<i64>,
+    pub message: String,
+    /// The measured value and the profile's limit, for numeric rules.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<f64>,
+}
+
+pub fn lint(document: &Document, profile: &Profile) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    let mut previous_end: Option<i64> = None;
+    let mut number = 0;
+    let mut position = 0;
+    for block in &document.blocks {
+        let cue = match block {
+            Block::Cue(cue) => cue,
+            Block::Other {
+                line,
+                malformed: Some(why),
+                ..
+            } => {
+                if profile.checks("malformed") {
+                    issues.push(Issue {
+                        rule: "malformed",
+                        cue: None,
+                        line: *line,
+                        start_ms: None,
+                        end_ms: None,
+                        message: format!("Not a cue: {why}"),
+                        value: None,
+                        limit: None,
+                    });
+                }
+                continue;
+            }
+            Block::Other { .. } => continue,
+        };
+        position += 1;
+        let mut report = |rule: &'static str, message: String, measured: Option<(f64, f64)>| {
+            if profile.checks(rule) {
+                issues.push(Issue {
+                    rule,
+                    cue: Some(position),
+                    line: cue.line,
+                    start_ms: Some(cue.start_ms),
+                    end_ms: Some(cue.end_ms),
+                    message,
+                    value: measured.map(|(value, _)| value),
+                    limit: measured.map(|(_, limit)| limit),
+                });
+            }
+        };
+
+        if document.format == Format::Srt {
+            let expected = number + 1;
+            number = cue.id.as_deref().and_then(|id| id.trim().parse().ok()).unwrap_or(expected);
+            if cue.id.as_deref().map(str::trim) != Some(expected.to_string().as_str()) {
+                let found = cue.id.as_deref().unwrap_or("missing");
+                report("numbering", format!("Cue number is {found}, expected {expected}"), None);
+            }
+        }
+
+        let duration = cue.end_ms - cue.start_ms;
+        if duration <= 0 {
+            report("non_positive_duration", format!("Cue lasts {duration} ms"), None);
+        } else if duration < profile.min_duration_ms {
+            let limit = profile.min_duration_ms;
+            report(
+                "min_duration",
+                format!("Cue lasts {duration} ms, under the {limit} ms minimum"),
+                Some((duration as f64, limit as f64)),
+            );
+        } else if duration > profile.max_duration_ms {
+            let limit = profile.max_duration_ms;
+            report(
+                "max_duration",
+                format!("Cue lasts {duration} ms, over the {limit} ms maximum"),
+                Some((duration as f64, limit as f64)),
+            );
+        }
+
+        if let Some(previous_end) = previous_end {
+            let gap = cue.start_ms - previous_end;
+            if gap < 0 {
+                report("overlap", format!("Cue overlaps the previous cue by {} ms", -gap), None);
+            } else if gap < profile.min_gap_ms() {
+                let limit = profile.min_gap_ms();
+                report(
+                    "gap",
+                    format!(
+                        "Gap of {gap} ms to the previous cue is under {} frames ({limit} ms)",
+                        profile.min_gap_frames
+                    ),
+                    Some((gap as f64, limit as f64)),
+                );
+            }
+        }
+        previous_end = Some(cue.end_ms);
+
+        let lines = cue
+            .visible_lines()
+            .into_iter()
+            .map(|line| line.trim().to_string())
+            .filter(|line| !line.is_empty())
+            .collect::<Vec<_>>();
+        if lines.is_empty() {
+            report("empty", "Cue has no text".to_string(), None);
+            continue;
+        }
+        let chars = lines.iter().map(|line| line.chars().count()).sum::<usize>();
+        if duration > 0 {
+            let cps = chars as f64 * 1000.0 / duration as f64;
+            if cps > profile.max_cps {
+                let cps = (cps * 10.0).round() / 10.0;
+                report(
+                    "cps",
+                    format!("Reading speed is {cps} characters per second, over {}", profile.max_cps),
+                    Some((cps, profile.max_cps)),
+                );
+            }
+        }
+        for (index, line) in lines.iter().enumerate() {
+            let len = line.chars().count();
+            if len > profile.max_line_chars {
+                report(
+                    "line_length",
+                    format!("Line {} has {len} characters, over {}", index + 1, profile.max_line_chars),
+                    Some((len as f64, profile.max_line_chars as f64)),
+                );
+            }
+        }
+        if lines.len() > profile.max_lines {
+            report(
+                "line_count",
+                format!("Cue has {} lines, over {}", lines.len(), profile.max_lines),
+                Some((lines.len() as f64, profile.max_lines as f64)),
+            );
+        }
+    }
+    issues
+}
+
+/// Issue counts by rule, for a quick overview.
+pub fn summary(issues: &[Issue]) -> BTreeMap<&'static str, usize> {
+    let mut counts = BTreeMap::new();
+    for issue in issues {
+        *counts.entry(issue.rule).or_insert(0) += 1;
+    }
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn rules(issues: &[Issue]) -> Vec<(&str, Option<usize>)> {
+        issues.iter().map(|issue| (issue.rule, issue.cue)).collect()
+    }
+
+    fn params(value: serde_json::Value) -> Params {
+        value.as_object().unwrap().clone()
+    }
+
+    #[test]
+    fn reports_every_rule() {
+        let srt = "1\n00:00:00,000 --> 00:00:02,000\nFine.\n\n\
+                   3\n00:00:02,020 --> 00:00:03,000\nThis line is far too long for a subtitle, really it is\nand\nmore\n\n\
+                   4\n00:00:02,500 --> 00:00:02,500\nZero.\n\n\
+                   00:00:04,000 --> 00:00:04,500\n<i> </i>\n\n\
+                   garbage\n\n\
+                   6\n00:00:05,000 --> 00:00:13,000\nLong.\n";
+        let document = Document::parse(srt, Format::Srt);
+        let issues = lint(&document, &Profile::default());
+        assert_eq!(
+            rules(&issues),
+            [
+                ("numbering", Some(2)),
+                ("min_duration", Some(2)),
+                ("gap", Some(2)),
+                ("cps", Some(2)),
+                ("line_length", Some(2)),
+                ("line_count", Some(2)),
+                ("non_positive_duration", Some(3)),
+                ("overlap", Some(3)),
+                ("numbering", Some(4)),
+                ("min_duration", Some(4)),
+                ("empty", Some(4)),
+                ("malformed", None),
+                ("max_duration", Some(5)),
+            ]
+        );
+        assert_eq!(issues[0].message, "Cue number is 3, expected 2");
+        assert_eq!(issues[2].message, "Gap of 20 ms to the previous cue is under 2 frames (80 ms)");
+        assert_eq!((issues[2].value, issues[2].limit), (Some(20.0), Some(80.0)));
+        assert_eq!(issues[3].message, "Reading speed is 62.2 characters per second, over 17");
+        assert_eq!(issues[4].message, "Line 1 has 54 characters, over 42");
+        assert_eq!(issues[7].message, "Cue overlaps the previous cue by 500 ms");
+        assert_eq!(issues[8].message, "Cue number is missing, expected 5");
+        assert_eq!(issues[11].message, "Not a cue: no timing line");
+        assert_eq!(
+            serde_json::to_value(&issues[11]).unwrap(),
+            json!({ "rule": "malformed", "line": 18, "message": "Not a cue: no timing line" })
+        );
+        // After a gap in numbering, counting continues from the number found.
+        assert_eq!(issues.iter().filter(|issue| issue.rule == "numbering").count(), 2);
+        let counts = summary(&issues);
+        assert_eq!(counts["numbering"], 2);
+        assert_eq!(counts["cps"], 1);
+
+        // Only the profile's rules run.
+        let only = Profile {
+            rules: vec!["overlap".to_string(), "malformed".to_string()],
+            ..Profile::default()
+        };
+        assert_eq!(rules(&lint(&document, &only)), [("overlap", Some(3)), ("malformed", None)]);
+    }
+
+    #[test]
+    fn skips_numbering_for_vtt_and_flags_reordered_cues() {
+        let vtt = "WEBVTT\n\nNOTE fine\n\n00:00:05.000 --> 00:00:07.000\nLater.\n\n00:00:01.000 --> 00:00:03.000\nEarlier.\n";
+        let issues = lint(&Document::parse(vtt, Format::Vtt), &Profile::default());
+        assert_eq!(rules(&issues), [("overlap", Some(2))]);
+        let no_gap = Profile {
+            min_gap_frames: 0,
+            ..Profile::default()
+        };
+        let touching = "1\n00:00:00,000 --> 00:00:01,000\nOne.\n\n2\n00:00:01,000 --> 00:00:02,000\nTwo.\n";
+        assert!(lint(&Document::parse(touching, Format::Srt), &no_gap).is_empty());
+        assert_eq!(rules(&lint(&Document::parse(touching, Format::Srt), &Profile::default())), [("gap", Some(2))]);
+    }
+
+    #[test]
+    fn resolves_profiles() {
+        assert_eq!(Profile::resolve("default", &BTreeMap::new(), None).unwrap(), Profile::default());
+        let streaming = Profile::resolve("streaming", &BTreeMap::new(), None).unwrap();
+        assert_eq!((streaming.max_cps, streaming.min_gap_ms()), (20.0, 83));
+        assert_eq!(Profile::builtin("broadcast").unwrap().max_line_chars, 37);
+        assert!(PROFILES.iter().all(|name| Profile::builtin(name).is_some()));
+
+        let custom = BTreeMap::from([
+            ("streaming".to_string(), params(json!({ "max_cps": 18 }))),
+            ("kids".to_string(), params(json!({ "max_cps": 12, "rules": ["cps"] }))),
+        ]);
+        let tuned = Profile::resolve("streaming", &custom, Some(&params(json!({ "max_lines": 3 })))).unwrap();
+        assert_eq!((tuned.max_cps, tuned.max_lines, tuned.fps), (18.0, 3, 24.0));
+        let kids = Profile::resolve("kids", &custom, None).unwrap();
+        assert_eq!((kids.max_cps, kids.rules.clone(), kids.max_line_chars), (12.0, vec!["cps".to_string()], 42));
+
+        let err = Profile::resolve("cinema", &custom, None).unwrap_err();
+        assert_eq!(crate::errors::code_and_data(&err).0, crate::errors::INVALID_PARAMS);
+        assert_eq!(
+            err.to_string(),
+            "Unknown lint profile: cinema (expected one of default, streaming, broadcast, kids)"
+        );
+        for (overrides, message) in [
+            (json!({ "max_cps": "fast" }), "Invalid lint profile default: invalid type"),
+            (json!({ "speed": 1 }), "Invalid lint profile default: unknown field `speed`"),
+            (json!({ "fps": 0 }), "max_line_chars, max_lines, max_cps and fps must be greater than 0"),
+            (json!({ "max_lines": 0 }), "max_line_chars, max_lines, max_cps and fps must be greater than 0"),
+            (json!({ "min_duration_ms": 9000 }), "min_duration_ms must not exceed max_duration_ms"),
+            (json!({ "rules": ["cps", "spelling"] }), "Unknown lint rule: spelling (expected one of malformed,"),
+        ] {
+            let err = Profile::resolve("default", &BTreeMap::new(), Some(&params(overrides))).unwrap_err();
+            assert!(err.to_string().starts_with(message), "{err}");
+        }
+    }
+}
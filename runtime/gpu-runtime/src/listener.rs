@@ -0,0 +1,620 @@
+//! Listener mode: serves the stdio protocol over a Unix socket or a loopback
+//! TCP port so several clients can share one runtime. Responses go to the
+//! client that asked; job events go to every connected client.
+
+use crate::errors::RuntimeError;
+use crate::{finish_shutdown, serve_requests, write_response, RpcError, RpcRequest, RpcResponse, Runtime, SharedWriter};
+use anyhow::{anyhow, bail, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+
+use crate::CHILD_POLL_INTERVAL;
+
+/// Environment variable holding the token clients must present.
+pub const TOKEN_ENV: &str = "AER_RUNTIME_TOKEN";
+
+/// Where the runtime listens: `unix:/path/to/socket` or `tcp:127.0.0.1:7878`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Endpoint {
+    #[cfg(unix)]
+    Unix(PathBuf),
+    Tcp(SocketAddr),
+}
+
+impl Endpoint {
+    pub fn parse(value: &str) -> Result<Self> {
+        if let Some(path) = value.strip_prefix("unix:") {
+            #[cfg(unix)]
+            {
+                if path.is_empty() {
+                    bail!("Unix socket endpoints need a path, e.g. unix:/tmp/subtly.sock");
+                }
+                return Ok(Self::Unix(PathBuf::from(path)));
+            }
+            #[cfg(not(unix))]
+            bail!("Unix sockets are not supported on this platform: {path}");
+        }
+        if let Some(addr) = value.strip_prefix("tcp:") {
+            let addr = addr
+                .parse::<SocketAddr>()
+                .map_err(|err| anyhow!("Invalid TCP address {addr}: {err}"))?;
+            if !addr.ip().is_loopback() {
+                bail!("TCP listeners must bind a loopback address, got {addr}");
+            }
+            return Ok(Self::Tcp(addr));
+        }
+        bail!("Unknown listen endpoint {value}; expected unix:<path> or tcp:<host>:<port>")
+    }
+}
+
+impl std::fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            #[cfg(unix)]
+            Self::Unix(path) => write!(f, "unix:{}", path.display()),
+            Self::Tcp(addr) => write!(f, "tcp:{addr}"),
+        }
+    }
+}
+
+/// Parsed `--listen <endpoint> [--token <token>]` arguments.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListenOptions {
+    pub endpoint: Endpoint,
+    /// Required for TCP; optional for Unix sockets, which are private to the
+    /// user already.
+    pub token: Option<String>,
+}
+
+/// Returns `None` when no `--listen` was given, i.e. the runtime should serve
+/// stdio. `env_token` is the value of [`TOKEN_ENV`], used when `--token` is absent.
+pub fn listen_options(
+    args: impl IntoIterator<Item = String>,
+    env_token: Option<String>,
+) -> Result<Option<ListenOptions>> {
+    let mut endpoint = None;
+    let mut token = None;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let slot = match arg.as_str() {
+            "--listen" => &mut endpoint,
+            "--token" => &mut token,
+            _ => bail!("Unknown argument: {arg}"),
+        };
+        *slot = Some(args.next().ok_or_else(|| anyhow!("{arg} needs a value"))?);
+    }
+    let Some(endpoint) = endpoint else {
+        return Ok(None);
+    };
+    let endpoint = Endpoint::parse(&endpoint)?;
+    let token = token.or(env_token).filter(|token| !token.is_empty());
+    if matches!(endpoint, Endpoint::Tcp(_)) && token.is_none() {
+        bail!("TCP listeners require a token (--token or {TOKEN_ENV})");
+    }
+    Ok(Some(ListenOptions { endpoint, token }))
+}
+
+/// Connected clients; writing to it sends the bytes to each of them and drops
+/// the ones that can no longer be written to.
+#[derive(Clone, Default)]
+pub struct Clients {
+    writers: Arc<Mutex<Vec<SharedWriter>>>,
+}
+
+impl Clients {
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<SharedWriter>> {
+        self.writers.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn add(&self, writer: SharedWriter) {
+        self.lock().push(writer);
+    }
+
+    fn remove(&self, writer: &SharedWriter) {
+        self.lock().retain(|client| !Arc::ptr_eq(&client.inner, &writer.inner));
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.lock().len()
+    }
+}
+
+impl Write for Clients {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.lock()
+            .retain_mut(|client| client.write_all(buf).and_then(|()| client.flush()).is_ok());
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A connection the listener can hand to a client thread and close on shutdown.
+trait Stream: Read + Write + Send + Sized + 'static {
+    fn duplicate(&self) -> io::Result<Self>;
+    fn set_blocking(&self) -> io::Result<()>;
+    fn close(&self);
+}
+
+impl Stream for TcpStream {
+    fn duplicate(&self) -> io::Result<Self> {
+        self.try_clone()
+    }
+
+    fn set_blocking(&self) -> io::Result<()> {
+        self.set_nonblocking(false)
+    }
+
+    fn close(&self) {
+        let _ = self.shutdown(Shutdown::Both);
+    }
+}
+
+#[cfg(unix)]
+impl Stream for UnixStream {
+    fn duplicate(&self) -> io::Result<Self> {
+        self.try_clone()
+    }
+
+    fn set_blocking(&self) -> io::Result<()> {
+        self.set_nonblocking(false)
+    }
+
+    fn close(&self) {
+        let _ = self.shutdown(Shutdown::Both);
+    }
+}
+
+/// A bound, not yet serving, listener.
+pub enum Bound {
+    #[cfg(unix)]
+    Unix(UnixListener, SocketFile),
+    Tcp(TcpListener),
+}
+
+/// Removes the socket file once the listener is gone.
+#[cfg(unix)]
+pub struct SocketFile(PathBuf);
+
+#[cfg(unix)]
+impl Drop for SocketFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+pub fn bind(endpoint: &Endpoint) -> Result<Bound> {
+    match endpoint {
+        #[cfg(unix)]
+        Endpoint::Unix(path) => {
+            use std::os::unix::fs::PermissionsExt;
+
+            if UnixStream::connect(path).is_ok() {
+                bail!("Another runtime is already listening on {}", path.display());
+            }
+            // A socket left behind by a runtime that did not exit cleanly.
+            match std::fs::remove_file(path) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err.into()),
+                _ => {}
+            }
+            let listener = UnixListener::bind(path)?;
+            let file = SocketFile(path.clone());
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+            listener.set_nonblocking(true)?;
+            Ok(Bound::Unix(listener, file))
+        }
+        Endpoint::Tcp(addr) => {
+            let listener = TcpListener::bind(addr)?;
+            listener.set_nonblocking(true)?;
+            Ok(Bound::Tcp(listener))
+        }
+    }
+}
+
+impl Bound {
+    /// The endpoint clients should connect to, with the actual port when `0`
+    /// was requested.
+    pub fn endpoint(&self) -> Result<Endpoint> {
+        Ok(match self {
+            #[cfg(unix)]
+            Bound::Unix(_, file) => Endpoint::Unix(file.0.clone()),
+            Bound::Tcp(listener) => Endpoint::Tcp(listener.local_addr()?),
+        })
+    }
+}
+
+/// Accepts clients until the runtime stops, then sends `runtime_stopped` to
+/// every client and disconnects them. `runtime.events` must write to `clients`.
+pub fn serve(bound: Bound, token: Option<&str>, runtime: &Runtime, clients: &Clients) -> Result<()> {
+    match bound {
+        #[cfg(unix)]
+        Bound::Unix(listener, _file) => {
+            accept_loop(|| listener.accept().map(|(stream, _)| stream), token, runtime, clients)
+        }
+        Bound::Tcp(listener) => {
+            accept_loop(|| listener.accept().map(|(stream, _)| stream), token, runtime, clients)
+        }
+    }
+}
+
+fn accept_loop<S: Stream>(
+    mut accept: impl FnMut() -> io::Result<S>,
+    token: Option<&str>,
+    runtime: &Runtime,
+    clients: &Clients,
+) -> Result<()> {
+    let open = Mutex::new(HashMap::<usize, S>::new());
+    thread::scope(|scope| {
+        let mut next_id = 0;
+        while !runtime.stopping.is_cancelled() {
+            let stream = match accept() {
+                Ok(stream) => stream,
+                Err(err) => {
+                    if err.kind() != io::ErrorKind::WouldBlock {
+                        eprintln!("Failed to accept a client: {err}");
+                    }
+                    thread::sleep(CHILD_POLL_INTERVAL);
+                    continue;
+                }
+            };
+            let closer = match stream.set_blocking().and_then(|()| stream.duplicate()) {
+                Ok(closer) => closer,
+                Err(err) => {
+                    eprintln!("Failed to set up a client: {err}");
+                    continue;
+                }
+            };
+            let id = next_id;
+            next_id += 1;
+            open.lock().unwrap_or_else(PoisonError::into_inner).insert(id, closer);
+            let open = &open;
+            scope.spawn(move || {
+                if let Err(err) = serve_client(stream, token, runtime, clients) {
+                    eprintln!("Client connection failed: {err:#}");
+                }
+                open.lock().unwrap_or_else(PoisonError::into_inner).remove(&id);
+            });
+        }
+
+        let result = finish_shutdown(runtime, "request", 0);
+        for stream in open.lock().unwrap_or_else(PoisonError::into_inner).values() {
+            stream.close();
+        }
+        clients.lock().clear();
+        result
+    })
+}
+
+fn serve_client<S: Stream>(stream: S, token: Option<&str>, runtime: &Runtime, clients: &Clients) -> Result<()> {
+    let writer = SharedWriter::new(stream.duplicate()?);
+    let mut reader = BufReader::new(stream);
+    if let Some(token) = token {
+        if !authenticate(&mut reader, token, &mut writer.clone())? {
+            return Ok(());
+        }
+    }
+    clients.add(writer.clone());
+    let client = Runtime {
+        writer: writer.clone(),
+        ..runtime.clone()
+    };
+    let result = serve_requests(reader, &client);
+    // Clients that asked for `shutdown` still get `runtime_stopped`.
+    if !runtime.stopping.is_cancelled() {
+        clients.remove(&writer);
+    }
+    result
+}
+
+#[derive(Deserialize)]
+struct AuthenticateParams {
+    token: String,
+}
+
+/// Reads the first request, which must be `authenticate { token }`, and
+/// answers it. Returns whether the client may continue.
+fn authenticate(reader: &mut impl BufRead, token: &str, writer: &mut SharedWriter) -> Result<bool> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(false);
+    }
+    let request = serde_json::from_str(&line)
+        .ok()
+        .and_then(|message| RpcRequest::from_value(message).ok());
+    let id = request
+        .as_ref()
+        .and_then(|request| request.id.clone())
+        .unwrap_or(serde_json::Value::Null);
+    let accepted = request.is_some_and(|request| {
+        request.method == "authenticate"
+            && serde_json::from_value::<AuthenticateParams>(request.params)
+                .is_ok_and(|params| tokens_match(&params.token, token))
+    });
+    let response = if accepted {
+        RpcResponse::success(id, serde_json::json!({ "authenticated": true }))
+    } else {
+        RpcResponse::failure(id, RpcError::from_error(&RuntimeError::Unauthorized.into()))
+    };
+    write_response(writer, response)?;
+    Ok(accepted)
+}
+
+/// Compares without stopping at the first differing byte, so response times
+/// do not reveal how much of a guess was right.
+fn tokens_match(given: &str, expected: &str) -> bool {
+    given.len() == expected.len()
+        && given
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors;
+    use crate::journal::Journal;
+    use serde_json::{json, Value};
+    use std::time::Duration;
+
+    fn args(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn parses_listen_options() {
+        assert_eq!(listen_options(args(&[]), Some("t".into())).unwrap(), None);
+
+        let options = listen_options(args(&["--listen", "tcp:127.0.0.1:7878", "--token", "secret"]), None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(options.endpoint, Endpoint::Tcp("127.0.0.1:7878".parse().unwrap()));
+        assert_eq!(options.endpoint.to_string(), "tcp:127.0.0.1:7878");
+        assert_eq!(options.token.as_deref(), Some("secret"));
+
+        let options = listen_options(args(&["--listen", "tcp:[::1]:1"]), Some("env".into()))
+            .unwrap()
+            .unwrap();
+        assert_eq!(options.token.as_deref(), Some("env"));
+
+        let errors = [
+            (args(&["--listen", "tcp:127.0.0.1:1"]), "require a token"),
+            (args(&["--listen", "tcp:0.0.0.0:1", "--token", "t"]), "loopback"),
+            (args(&["--listen", "tcp:localhost"]), "Invalid TCP address"),
+            (args(&["--listen", "pipe:x"]), "Unknown listen endpoint"),
+            (args(&["--listen"]), "--listen needs a value"),
+            (args(&["--verbose"]), "Unknown argument: --verbose"),
+        ];
+        for (args, message) in errors {
+            let err = listen_options(args, Some(String::new())).unwrap_err();
+            assert!(err.to_string().contains(message), "{err}");
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn parses_unix_endpoints() {
+        let options = listen_options(args(&["--listen", "unix:/tmp/rt.sock"]), None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(options.endpoint, Endpoint::Unix(PathBuf::from("/tmp/rt.sock")));
+        assert_eq!(options.endpoint.to_string(), "unix:/tmp/rt.sock");
+        assert_eq!(options.token, None);
+        assert!(Endpoint::parse("unix:").is_err());
+    }
+
+    #[test]
+    fn compares_tokens() {
+        assert!(tokens_match("secret", "secret"));
+        assert!(!tokens_match("secreT", "secret"));
+        assert!(!tokens_match("secret!", "secret"));
+    }
+
+    #[test]
+    fn clients_drop_dead_writers() {
+        struct Broken;
+        impl Write for Broken {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                Err(io::Error::other("gone"))
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+        let mut broken = Broken;
+        assert!(broken.flush().is_ok());
+
+        let mut clients = Clients::default();
+        let alive = SharedWriter::new(Vec::new());
+        clients.add(alive.clone());
+        clients.add(SharedWriter::new(Broken));
+        clients.write_all(b"event\n").unwrap();
+        clients.flush().unwrap();
+        assert_eq!(clients.len(), 1);
+        clients.remove(&alive);
+        assert_eq!(clients.len(), 0);
+    }
+
+    struct Client {
+        reader: BufReader<Box<dyn Read + Send>>,
+        writer: Box<dyn Write + Send>,
+    }
+
+    impl Client {
+        fn tcp(endpoint: &Endpoint) -> Self {
+            let Endpoint::Tcp(addr) = endpoint else {
+                unreachable!()
+            };
+            let stream = TcpStream::connect(addr).unwrap();
+            stream.set_read_timeout(Some(Duration::from_secs(20))).unwrap();
+            Self {
+                reader: BufReader::new(Box::new(stream.try_clone().unwrap())),
+                writer: Box::new(stream),
+            }
+        }
+
+        fn send(&mut self, message: Value) {
+            writeln!(self.writer, "{message}").unwrap();
+        }
+
+        /// Reads messages until one matches, returning it; `None` on EOF.
+        fn until(&mut self, matches: impl Fn(&Value) -> bool) -> Option<Value> {
+            loop {
+                let mut line = String::new();
+                if self.reader.read_line(&mut line).unwrap() == 0 {
+                    return None;
+                }
+                let message = serde_json::from_str::<Value>(&line).unwrap();
+                if matches(&message) {
+                    return Some(message);
+                }
+            }
+        }
+
+        fn call(&mut self, id: u64, method: &str, params: Value) -> Value {
+            self.send(json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }));
+            self.until(|message| message["id"] == id).unwrap()
+        }
+    }
+
+    fn listening_runtime() -> (Runtime, Clients) {
+        let clients = Clients::default();
+        let runtime = Runtime {
+            events: SharedWriter::new(clients.clone()),
+            ..Runtime::with_journal(SharedWriter::new(io::sink()), Journal::in_memory())
+        };
+        (runtime, clients)
+    }
+
+    #[test]
+    fn tcp_clients_share_job_events() {
+        let temp = tempfile::tempdir().unwrap();
+        let media = temp.path().join("clip.mp4");
+        std::fs::write(&media, "x").unwrap();
+
+        let (runtime, clients) = listening_runtime();
+        let bound = bind(&Endpoint::parse("tcp:127.0.0.1:0").unwrap()).unwrap();
+        let endpoint = bound.endpoint().unwrap();
+        let server = {
+            let (runtime, clients) = (runtime.clone(), clients.clone());
+            thread::spawn(move || serve(bound, Some("secret"), &runtime, &clients))
+        };
+
+        let mut intruder = Client::tcp(&endpoint);
+        let denied = intruder.call(1, "authenticate", json!({ "token": "guess" }));
+        assert_eq!(denied["error"]["code"], errors::UNAUTHORIZED);
+        assert_eq!(intruder.until(|_| true), None);
+
+        let mut skipper = Client::tcp(&endpoint);
+        let denied = skipper.call(1, "ping", json!({}));
+        assert_eq!(denied["error"]["code"], errors::UNAUTHORIZED);
+
+        // Connecting and leaving without a word is harmless.
+        drop(Client::tcp(&endpoint));
+
+        let mut app = Client::tcp(&endpoint);
+        let mut script = Client::tcp(&endpoint);
+        for client in [&mut app, &mut script] {
+            let accepted = client.call(1, "authenticate", json!({ "token": "secret" }));
+            assert_eq!(accepted["result"], json!({ "authenticated": true }));
+        }
+        while clients.len() < 2 {
+            thread::sleep(Duration::from_millis(5));
+        }
+        // Authenticating again once in is a no-op.
+        let again = app.call(2, "authenticate", json!({ "token": "secret" }));
+        assert_eq!(again["result"]["authenticated"], true);
+
+        let submitted = script.call(
+            3,
+            "transcribe",
+            json!({ "input_path": media.to_string_lossy(), "dry_run": true }),
+        );
+        let job_id = submitted["result"]["job_id"].clone();
+        for client in [&mut app, &mut script] {
+            let completed = client
+                .until(|message| message["method"] == "job_completed")
+                .unwrap();
+            assert_eq!(completed["params"]["job_id"], job_id);
+        }
+
+        let stopped = app.call(4, "shutdown", json!({}));
+        assert_eq!(stopped["result"]["cancelled_jobs"], 0);
+        for client in [&mut app, &mut script] {
+            let event = client
+                .until(|message| message["method"] == "runtime_stopped")
+                .unwrap();
+            assert_eq!(event["params"], json!({ "reason": "request", "exit_code": 0 }));
+            assert_eq!(client.until(|_| true), None);
+        }
+        server.join().unwrap().unwrap();
+        assert_eq!(clients.len(), 0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unix_socket_clients_need_no_token() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("runtime.sock");
+        // Left behind by a runtime that crashed.
+        drop(UnixListener::bind(&path).unwrap());
+
+        let (runtime, clients) = listening_runtime();
+        let endpoint = Endpoint::Unix(path.clone());
+        let bound = bind(&endpoint).unwrap();
+        assert_eq!(bound.endpoint().unwrap(), endpoint);
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        let err = bind(&endpoint).err().unwrap();
+        assert!(err.to_string().contains("already listening"), "{err}");
+
+        let server = {
+            let runtime = runtime.clone();
+            thread::spawn(move || serve(bound, None, &runtime, &clients))
+        };
+        let stream = UnixStream::connect(&path).unwrap();
+        let mut client = Client {
+            reader: BufReader::new(Box::new(stream.try_clone().unwrap())),
+            writer: Box::new(stream),
+        };
+        assert_eq!(client.call(1, "job_list", json!({}))["result"], json!({ "jobs": [] }));
+
+        runtime.begin_shutdown();
+        let event = client
+            .until(|message| message["method"] == "runtime_stopped")
+            .unwrap();
+        assert_eq!(event["params"]["reason"], "request");
+        server.join().unwrap().unwrap();
+        assert!(!path.exists());
+
+        let dir = temp.path().join("missing").join("runtime.sock");
+        assert!(bind(&Endpoint::Unix(dir)).is_err());
+    }
+
+    #[test]
+    fn authentication_needs_a_request() {
+        let mut writer = SharedWriter::new(Vec::new());
+        assert!(!authenticate(&mut io::Cursor::new(""), "t", &mut writer).unwrap());
+        assert!(!authenticate(&mut io::Cursor::new("not json\n"), "t", &mut writer).unwrap());
+        assert!(authenticate(
+            &mut io::Cursor::new(r#"{"method":"authenticate","params":{"token":"t"}}"#),
+            "t",
+            &mut writer
+        )
+        .unwrap());
+    }
+}
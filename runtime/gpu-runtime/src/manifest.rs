@@ -0,0 +1,293 @@
+//! `<name>.subtly.json` next to a file's outputs records what produced them:
+//! the input and model content hashes and the settings that shape the
+//! subtitles. A file is only transcribed again when one of those changed.
+
+use crate::TranscribeConfig;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock, PoisonError};
+use std::time::UNIX_EPOCH;
+
+const VERSION: u32 = 1;
+
+pub fn path_for(output_base: &Path) -> PathBuf {
+    output_base.with_extension("subtly.json")
+}
+
+/// A file's content hash, with the size and mtime it was taken at so an
+/// unchanged file does not have to be read again.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Fingerprint {
+    path: String,
+    size: u64,
+    modified_ns: u64,
+    sha256: String,
+}
+
+/// Hashes computed by this process, so a model shared by a whole batch is
+/// read once.
+fn cache() -> &'static Mutex<HashMap<String, Fingerprint>> {
+    static CACHE: OnceLock<Mutex<HashMap<String, Fingerprint>>> = OnceLock::new();
+    CACHE.get_or_init(Default::default)
+}
+
+impl Fingerprint {
+    pub fn sha256(&self) -> &str {
+        &self.sha256
+    }
+
+    /// Reuses the hash of `known` or of an earlier call when the file still
+    /// has the same size and mtime.
+    pub fn of(path: &Path, known: Option<&Fingerprint>) -> Result<Self> {
+        let meta = fs::metadata(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        let modified_ns = meta
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |since| since.as_nanos() as u64);
+        let path = path.display().to_string();
+        let unchanged = |fingerprint: &&Fingerprint| {
+            fingerprint.path == path && fingerprint.size == meta.len() && fingerprint.modified_ns == modified_ns
+        };
+        if let Some(fingerprint) = known.filter(unchanged) {
+            return Ok(fingerprint.clone());
+        }
+        if let Some(fingerprint) = cache().lock().unwrap_or_else(PoisonError::into_inner).get(&path).filter(unchanged) {
+            return Ok(fingerprint.clone());
+        }
+        let fingerprint = Self {
+            sha256: file_sha256(Path::new(&path))?,
+            path: path.clone(),
+            size: meta.len(),
+            modified_ns,
+        };
+        cache().lock().unwrap_or_else(PoisonError::into_inner).insert(path, fingerprint.clone());
+        Ok(fingerprint)
+    }
+}
+
+/// Lowercase hex SHA-256 of a file's contents.
+fn file_sha256(path: &Path) -> Result<String> {
+    let read_error = || format!("Failed to read {}", path.display());
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path).with_context(read_error)?, &mut hasher).with_context(read_error)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    version: u32,
+    input: Fingerprint,
+    model: Fingerprint,
+    vad_model: Fingerprint,
+    settings: serde_json::Value,
+    outputs: Vec<String>,
+}
+
+impl Manifest {
+    pub fn build(input: &Path, config: &TranscribeConfig, outputs: &[PathBuf], previous: Option<&Manifest>) -> Result<Self> {
+        Ok(Self {
+            version: VERSION,
+            input: Fingerprint::of(input, previous.map(|manifest| &manifest.input))?,
+            model: Fingerprint::of(Path::new(&config.model_path), previous.map(|manifest| &manifest.model))?,
+            vad_model: Fingerprint::of(
+                Path::new(&config.vad_model_path),
+                previous.map(|manifest| &manifest.vad_model),
+            )?,
+            settings: settings(config),
+            outputs: outputs.iter().map(|output| output.display().to_string()).collect(),
+        })
+    }
+
+    /// A missing or unreadable manifest is treated as absent.
+    pub fn load(path: &Path) -> Option<Self> {
+        let text = fs::read_to_string(path).ok()?;
+        serde_json::from_str(&text).ok()
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let text = serde_json::to_string_pretty(self)?;
+        fs::write(path, text + "\n").with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// Same content and settings; where the files live does not matter.
+    fn matches(&self, other: &Manifest) -> bool {
+        self.version == other.version
+            && self.input.sha256 == other.input.sha256
+            && self.model.sha256 == other.model.sha256
+            && self.vad_model.sha256 == other.vad_model.sha256
+            && self.settings == other.settings
+            && self.outputs == other.outputs
+    }
+}
+
+/// Whether `outputs` exist and were produced from the same input, models
+/// and settings. Anything that cannot be checked counts as out of date.
+pub fn is_current(manifest_path: &Path, input: &Path, config: &TranscribeConfig, outputs: &[PathBuf]) -> bool {
+    let Some(previous) = Manifest::load(manifest_path) else {
+        return false;
+    };
+    if !outputs.iter().all(|output| output.is_file()) {
+        return false;
+    }
+    Manifest::build(input, config, outputs, Some(&previous)).is_ok_and(|current| current.matches(&previous))
+}
+
+/// The settings that change what gets written. Paths, threads, devices and
+/// concurrency only change how fast.
+pub fn settings(config: &TranscribeConfig) -> serde_json::Value {
+    json!({
+        "language": config.language,
+        "translate": config.translate,
+        "beam_size": config.beam_size,
+        "best_of": config.best_of,
+        "max_len_chars": config.max_len_chars,
+        "split_on_word": config.split_on_word,
+        "vad_threshold": config.vad_threshold,
+        "vad_min_speech_ms": config.vad_min_speech_ms,
+        "vad_min_sil_ms": config.vad_min_sil_ms,
+        "vad_pad_ms": config.vad_pad_ms,
+        "no_speech_thold": config.no_speech_thold,
+        "max_context": config.max_context,
+        "dedup_merge_gap_sec": config.dedup_merge_gap_sec,
+        "flash_attn": config.flash_attn,
+        "output_formats": config.output_formats,
+        "review": config.review,
+        "review_threshold": config.review_threshold,
+        "review_speech": config.review_speech.name(),
+        "review_speech_threshold": config.review_speech_threshold,
+        "hallucination_filters": config.hallucination_filters,
+        "hallucination_phrases": config.hallucination_phrases,
+        "reflow": config.reflow,
+        "fix": config.fix
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transcribe_config;
+
+    struct Fixture {
+        _temp: tempfile::TempDir,
+        input: PathBuf,
+        outputs: Vec<PathBuf>,
+        manifest: PathBuf,
+        config: TranscribeConfig,
+    }
+
+    fn fixture() -> Fixture {
+        let temp = tempfile::tempdir().unwrap();
+        let input = temp.path().join("clip.mp4");
+        let model = temp.path().join("model.bin");
+        let vad = temp.path().join("vad.bin");
+        fs::write(&input, "video").unwrap();
+        fs::write(&model, "weights").unwrap();
+        fs::write(&vad, "silero").unwrap();
+        let outputs = vec![temp.path().join("clip.srt")];
+        fs::write(&outputs[0], "1\n").unwrap();
+        let config = transcribe_config(&json!({
+            "input_path": input.to_string_lossy(),
+            "model_path": model.to_string_lossy(),
+            "vad_model_path": vad.to_string_lossy()
+        }))
+        .unwrap();
+        Fixture {
+            manifest: path_for(&temp.path().join("clip")),
+            _temp: temp,
+            input,
+            outputs,
+            config,
+        }
+    }
+
+    fn write_manifest(fixture: &Fixture) -> Manifest {
+        let manifest = Manifest::build(&fixture.input, &fixture.config, &fixture.outputs, None).unwrap();
+        manifest.write(&fixture.manifest).unwrap();
+        manifest
+    }
+
+    #[test]
+    fn records_hashes_and_settings() {
+        let fixture = fixture();
+        assert!(fixture.manifest.ends_with("clip.subtly.json"));
+        let manifest = write_manifest(&fixture);
+        assert_eq!(Manifest::load(&fixture.manifest), Some(manifest.clone()));
+        assert_eq!(manifest.input.sha256, format!("{:x}", Sha256::digest(b"video")));
+        assert_eq!(manifest.model.sha256, format!("{:x}", Sha256::digest(b"weights")));
+        assert_eq!(manifest.settings["language"], "auto");
+        assert_eq!(manifest.settings["output_formats"], json!(["srt"]));
+        assert!(manifest.settings.get("threads").is_none());
+
+        let err = manifest.write(&fixture.input.join("x.json")).unwrap_err();
+        assert!(err.to_string().starts_with("Failed to write"), "{err}");
+    }
+
+    #[test]
+    fn is_current_until_something_changes() {
+        let fixture = fixture();
+        assert!(!is_current(&fixture.manifest, &fixture.input, &fixture.config, &fixture.outputs));
+        write_manifest(&fixture);
+        assert!(is_current(&fixture.manifest, &fixture.input, &fixture.config, &fixture.outputs));
+
+        // Threads do not change the subtitles; the language does.
+        let faster = TranscribeConfig { threads: 64, ..fixture.config.clone() };
+        assert!(is_current(&fixture.manifest, &fixture.input, &faster, &fixture.outputs));
+        let german = TranscribeConfig { language: "de".to_string(), ..fixture.config.clone() };
+        assert!(!is_current(&fixture.manifest, &fixture.input, &german, &fixture.outputs));
+
+        // The same model under another name is still the same model.
+        let copy = fixture.input.with_file_name("copy.bin");
+        fs::copy(&fixture.config.model_path, &copy).unwrap();
+        let copied = TranscribeConfig { model_path: copy.display().to_string(), ..fixture.config.clone() };
+        assert!(is_current(&fixture.manifest, &fixture.input, &copied, &fixture.outputs));
+        fs::write(&copy, "other weights").unwrap();
+        assert!(!is_current(&fixture.manifest, &fixture.input, &copied, &fixture.outputs));
+
+        // Touching the input without changing it keeps the outputs.
+        fs::write(&fixture.input, "video").unwrap();
+        assert!(is_current(&fixture.manifest, &fixture.input, &fixture.config, &fixture.outputs));
+        fs::write(&fixture.input, "new video").unwrap();
+        assert!(!is_current(&fixture.manifest, &fixture.input, &fixture.config, &fixture.outputs));
+    }
+
+    #[test]
+    fn missing_outputs_or_files_are_out_of_date() {
+        let fixture = fixture();
+        write_manifest(&fixture);
+        fs::remove_file(&fixture.config.vad_model_path).unwrap();
+        assert!(!is_current(&fixture.manifest, &fixture.input, &fixture.config, &fixture.outputs));
+
+        let fixture = self::fixture();
+        write_manifest(&fixture);
+        let more = [fixture.outputs.clone(), vec![fixture.input.with_extension("vtt")]].concat();
+        assert!(!is_current(&fixture.manifest, &fixture.input, &fixture.config, &more));
+        fs::remove_file(&fixture.outputs[0]).unwrap();
+        assert!(!is_current(&fixture.manifest, &fixture.input, &fixture.config, &fixture.outputs));
+
+        fs::write(&fixture.manifest, "{").unwrap();
+        assert_eq!(Manifest::load(&fixture.manifest), None);
+    }
+
+    #[test]
+    fn reuses_hashes_of_unchanged_files() {
+        let fixture = fixture();
+        let first = Fingerprint::of(&fixture.input, None).unwrap();
+        let known = Fingerprint {
+            sha256: "recorded".to_string(),
+            ..first.clone()
+        };
+        assert_eq!(Fingerprint::of(&fixture.input, Some(&known)).unwrap().sha256, "recorded");
+        let moved = Fingerprint {
+            path: "/elsewhere/clip.mp4".to_string(),
+            ..known
+        };
+        assert_eq!(Fingerprint::of(&fixture.input, Some(&moved)).unwrap(), first);
+    }
+}
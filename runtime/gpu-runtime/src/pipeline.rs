@@ -0,0 +1,363 @@
+//! Two-stage executor for batch jobs: a pool of extract workers feeds a
+//! bounded queue drained by a pool of decode workers, while the calling thread
+//! writes their events and reports results in input order.
+
+use crate::errors::{self, RuntimeError};
+use crate::jobs::CancelToken;
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Mutex, PoisonError};
+use std::thread;
+
+use crate::CHILD_POLL_INTERVAL;
+
+/// Worker counts for each stage; both must be at least one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    pub extract: usize,
+    pub decode: usize,
+}
+
+/// Context handed to a stage for one item.
+pub struct Worker {
+    /// Position of the item in the input list.
+    pub index: usize,
+    /// Which worker of its stage is running, from `0` to the stage limit.
+    pub slot: usize,
+    /// Events written here reach the caller's writer whole, one flush at a time.
+    pub out: EventWriter,
+    /// Set when the job is cancelled or another item failed.
+    pub cancel: CancelToken,
+}
+
+enum Message<T> {
+    Output(Vec<u8>),
+    Done(usize, Result<T>),
+}
+
+/// Buffers one event and hands it to the coordinating thread on flush.
+pub struct EventWriter {
+    pending: Vec<u8>,
+    send: Box<dyn Fn(Vec<u8>) -> bool + Send>,
+}
+
+impl EventWriter {
+    fn new<T: Send + 'static>(sender: &Sender<Message<T>>) -> Self {
+        let sender = sender.clone();
+        Self {
+            pending: Vec::new(),
+            send: Box::new(move |bytes| sender.send(Message::Output(bytes)).is_ok()),
+        }
+    }
+}
+
+impl Write for EventWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let pending = std::mem::take(&mut self.pending);
+        if (self.send)(pending) {
+            Ok(())
+        } else {
+            Err(io::Error::new(io::ErrorKind::BrokenPipe, "job output closed"))
+        }
+    }
+}
+
+/// Runs `extract` then `decode` for every item and calls `on_done` with the
+/// results in input order. The first failure cancels the remaining work and is
+/// returned once every worker has stopped; cancelling `cancel` yields
+/// `RuntimeError::Cancelled`.
+pub fn run<I, A, B, W>(
+    items: Vec<I>,
+    limits: Limits,
+    cancel: &CancelToken,
+    out: &mut W,
+    extract: impl Fn(&mut Worker, I) -> Result<A> + Sync,
+    decode: impl Fn(&mut Worker, A) -> Result<B> + Sync,
+    mut on_done: impl FnMut(&mut W, usize, B) -> Result<()>,
+) -> Result<()>
+where
+    I: Send,
+    A: Send,
+    B: Send + 'static,
+    W: Write,
+{
+    let abort = CancelToken::default();
+    let queue = Mutex::new(items.into_iter().enumerate());
+    // Holds at most one extracted item per decode worker, which bounds how
+    // many temp WAVs exist at once.
+    let (extracted_tx, extracted_rx) = mpsc::sync_channel::<(usize, A)>(limits.decode);
+    let extracted_rx = Mutex::new(extracted_rx);
+    let (sender, receiver) = mpsc::channel::<Message<B>>();
+
+    let worker = |index, slot, sender: &Sender<Message<B>>| Worker {
+        index,
+        slot,
+        out: EventWriter::new(sender),
+        cancel: abort.clone(),
+    };
+
+    thread::scope(|scope| {
+        for slot in 0..limits.extract {
+            let (sender, extracted_tx) = (sender.clone(), extracted_tx.clone());
+            let (queue, extract, worker, abort) = (&queue, &extract, &worker, &abort);
+            scope.spawn(move || loop {
+                if abort.is_cancelled() {
+                    break;
+                }
+                let next = queue.lock().unwrap_or_else(PoisonError::into_inner).next();
+                let Some((index, item)) = next else {
+                    break;
+                };
+                let mut context = worker(index, slot, &sender);
+                match extract(&mut context, item) {
+                    Ok(extracted) => {
+                        if extracted_tx.send((index, extracted)).is_err() {
+                            break;
+                        }
+                    }
+                    Err(err) => {
+                        let _ = sender.send(Message::Done(index, Err(err)));
+                    }
+                }
+            });
+        }
+        drop(extracted_tx);
+
+        for slot in 0..limits.decode {
+            let sender = sender.clone();
+            let (extracted_rx, decode, worker, abort) = (&extracted_rx, &decode, &worker, &abort);
+            scope.spawn(move || loop {
+                let next = extracted_rx
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .recv();
+                let Ok((index, extracted)) = next else {
+                    break;
+                };
+                if abort.is_cancelled() {
+                    continue;
+                }
+                let mut context = worker(index, slot, &sender);
+                let result = decode(&mut context, extracted);
+                let _ = sender.send(Message::Done(index, result));
+            });
+        }
+        drop(sender);
+
+        let mut failure = None;
+        let fail = |err: anyhow::Error, failure: &mut Option<anyhow::Error>| {
+            abort.cancel();
+            // Items stopped by the abort report `Cancelled`; keep the cause.
+            if failure.is_none() || failure.as_ref().is_some_and(errors::is_cancelled) {
+                *failure = Some(err);
+            }
+        };
+        let mut finished = BTreeMap::new();
+        let mut next_index = 0;
+        loop {
+            if cancel.is_cancelled() {
+                abort.cancel();
+            }
+            match receiver.recv_timeout(CHILD_POLL_INTERVAL) {
+                Ok(Message::Output(bytes)) => {
+                    if let Err(err) = out.write_all(&bytes).and_then(|()| out.flush()) {
+                        fail(err.into(), &mut failure);
+                    }
+                }
+                Ok(Message::Done(index, Ok(done))) => {
+                    finished.insert(index, done);
+                    while failure.is_none() {
+                        let Some(done) = finished.remove(&next_index) else {
+                            break;
+                        };
+                        if let Err(err) = on_done(out, next_index, done) {
+                            fail(err, &mut failure);
+                        }
+                        next_index += 1;
+                    }
+                }
+                Ok(Message::Done(_, Err(err))) => fail(err, &mut failure),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+
+        if cancel.is_cancelled() {
+            return Err(RuntimeError::Cancelled.into());
+        }
+        match failure {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::write_event;
+    use anyhow::anyhow;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    fn limits(extract: usize, decode: usize) -> Limits {
+        Limits { extract, decode }
+    }
+
+    #[test]
+    fn reports_results_in_input_order() {
+        let mut out = Vec::new();
+        let mut done = Vec::new();
+        let decoding = AtomicUsize::new(0);
+        let peak = AtomicUsize::new(0);
+        run(
+            vec![30u64, 0, 20, 5, 10],
+            limits(3, 2),
+            &CancelToken::default(),
+            &mut out,
+            |worker, delay| {
+                assert!(worker.slot < 3);
+                write_event(&mut worker.out, "extracted", json!({ "index": worker.index }))?;
+                Ok(delay)
+            },
+            |worker, delay| {
+                let running = decoding.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(running, Ordering::SeqCst);
+                thread::sleep(Duration::from_millis(delay));
+                decoding.fetch_sub(1, Ordering::SeqCst);
+                Ok((worker.index, worker.slot))
+            },
+            |_, index, (decoded_index, slot)| {
+                assert_eq!(index, decoded_index);
+                assert!(slot < 2);
+                done.push(index);
+                Ok(())
+            },
+        )
+        .unwrap();
+        assert_eq!(done, vec![0, 1, 2, 3, 4]);
+        assert!(peak.load(Ordering::SeqCst) <= 2);
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(out.lines().count(), 5);
+        assert!(out.lines().all(|line| line.contains("\"extracted\"")));
+    }
+
+    #[test]
+    fn first_failure_stops_remaining_items() {
+        let started = AtomicUsize::new(0);
+        let err = run(
+            (0..20).collect(),
+            limits(1, 1),
+            &CancelToken::default(),
+            &mut Vec::new(),
+            |_, item: usize| {
+                started.fetch_add(1, Ordering::SeqCst);
+                if item == 2 {
+                    return Err(anyhow!("extract failed"));
+                }
+                Ok(item)
+            },
+            |worker, item| {
+                if item == 1 {
+                    // Let the failure reach the coordinator first.
+                    while !worker.cancel.is_cancelled() {
+                        thread::sleep(Duration::from_millis(1));
+                    }
+                    return Err(RuntimeError::Cancelled.into());
+                }
+                Ok(item)
+            },
+            |_, _, _| Ok(()),
+        )
+        .unwrap_err();
+        assert_eq!(err.to_string(), "extract failed");
+        assert!(started.load(Ordering::SeqCst) < 20);
+
+        let err = run(
+            vec![0, 1],
+            limits(1, 1),
+            &CancelToken::default(),
+            &mut Vec::new(),
+            |_, item: usize| Ok(item),
+            |_, item| match item {
+                0 => Ok(item),
+                _ => Err(anyhow!("decode failed")),
+            },
+            |_, _, _| Err(anyhow!("report failed")),
+        )
+        .unwrap_err();
+        assert_eq!(err.to_string(), "report failed");
+    }
+
+    #[test]
+    fn cancelling_the_job_stops_workers() {
+        let cancel = CancelToken::default();
+        let err = run(
+            (0..100).collect(),
+            limits(2, 1),
+            &cancel,
+            &mut Vec::new(),
+            |_, item: usize| Ok(item),
+            |worker, item| {
+                cancel.cancel();
+                while !worker.cancel.is_cancelled() {
+                    thread::sleep(Duration::from_millis(1));
+                }
+                Ok(item)
+            },
+            |_, _, _| Ok(()),
+        )
+        .unwrap_err();
+        assert!(errors::is_cancelled(&err));
+    }
+
+    #[test]
+    fn reports_output_write_failures() {
+        struct Broken;
+        impl Write for Broken {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                Err(io::Error::other("broken"))
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+        let mut out = Broken;
+        assert!(out.flush().is_ok());
+        let err = run(
+            vec![0],
+            limits(1, 1),
+            &CancelToken::default(),
+            &mut out,
+            |worker, item: usize| {
+                worker.out.flush()?;
+                write_event(&mut worker.out, "log", json!({}))?;
+                Ok(item)
+            },
+            |_, item| Ok(item),
+            |_, _, _| Ok(()),
+        )
+        .unwrap_err();
+        assert_eq!(err.to_string(), "broken");
+    }
+
+    #[test]
+    fn event_writer_fails_once_the_coordinator_is_gone() {
+        let (sender, receiver) = mpsc::channel::<Message<()>>();
+        drop(receiver);
+        let mut writer = EventWriter::new(&sender);
+        writer.write_all(b"x").unwrap();
+        assert_eq!(writer.flush().unwrap_err().kind(), io::ErrorKind::BrokenPipe);
+    }
+}
@@ -0,0 +1,318 @@
+//! Named transcription presets from a shared config file. A request's params
+//! are layered over its `preset`, which is layered over the config's
+//! `defaults`, which are layered over the built-in defaults.
+
+use crate::capabilities::TRANSCRIBE_PARAMS;
+use crate::errors::RuntimeError;
+use crate::transcribe_config;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+type Params = serde_json::Map<String, serde_json::Value>;
+
+/// Contents of `config.toml` (or `config.json`):
+///
+/// ```toml
+/// [defaults]
+/// language = "de"
+///
+/// [presets.broadcast]
+/// max_len_chars = 42
+/// beam_size = 8
+///
+/// [lint_profiles.broadcast]
+/// max_cps = 14
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default)]
+    pub defaults: Params,
+    #[serde(default)]
+    pub presets: BTreeMap<String, Params>,
+    /// `lint_subtitles` rule profiles, checked when they are used.
+    #[serde(default)]
+    pub lint_profiles: BTreeMap<String, Params>,
+}
+
+impl Config {
+    /// Reads a TOML file, or JSON when the extension is `.json`. A missing
+    /// file is an empty config.
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(err).with_context(|| format!("Failed to read {}", path.display())),
+        };
+        let parsed = if path.extension().is_some_and(|ext| ext == "json") {
+            serde_json::from_str(&text).map_err(|err| err.to_string())
+        } else {
+            toml::from_str(&text).map_err(|err| err.message().to_string())
+        };
+        parsed.map_err(|message| {
+            RuntimeError::InvalidConfig {
+                path: path.display().to_string(),
+                message,
+            }
+            .into()
+        })
+    }
+
+    /// Layers `params` over its `preset` and the config defaults. The `preset`
+    /// name is kept so journaled jobs show where their settings came from.
+    pub fn resolve(&self, params: &serde_json::Value) -> Result<serde_json::Value> {
+        // Leave reporting malformed params to `transcribe_config`.
+        let serde_json::Value::Object(request) = params else {
+            return Ok(params.clone());
+        };
+        let mut merged = self.defaults.clone();
+        match request.get("preset") {
+            None | Some(serde_json::Value::Null) => {}
+            Some(serde_json::Value::String(name)) => {
+                let preset = self
+                    .presets
+                    .get(name)
+                    .ok_or_else(|| RuntimeError::InvalidParams(format!("Unknown preset: {name}")))?;
+                merged.extend(preset.clone());
+            }
+            Some(_) => return Err(RuntimeError::InvalidParams("preset must be a string".to_string()).into()),
+        }
+        merged.extend(request.clone());
+        Ok(serde_json::Value::Object(merged))
+    }
+
+    /// Describes the defaults and every preset, each with whether it yields a
+    /// usable transcribe config.
+    pub fn report(&self, path: Option<&Path>) -> serde_json::Value {
+        let check = |layers: &[&Params]| match validate(layers) {
+            Ok(()) => json!({ "valid": true, "error": null }),
+            Err(err) => json!({ "valid": false, "error": err.to_string() }),
+        };
+        let mut defaults = check(&[&self.defaults]);
+        defaults["params"] = json!(self.defaults);
+        let presets = self
+            .presets
+            .iter()
+            .map(|(name, params)| {
+                let mut preset = check(&[&self.defaults, params]);
+                preset["name"] = json!(name);
+                preset["params"] = json!(params);
+                preset
+            })
+            .collect::<Vec<_>>();
+        let valid = defaults["valid"] == true && presets.iter().all(|preset| preset["valid"] == true);
+        json!({
+            "path": path.map(|path| path.display().to_string()),
+            "valid": valid,
+            "defaults": defaults,
+            "presets": presets
+        })
+    }
+}
+
+/// Checks that the layered params only name known settings and that their
+/// values are accepted by `transcribe`.
+fn validate(layers: &[&Params]) -> Result<()> {
+    let mut merged = Params::new();
+    for layer in layers {
+        for (key, value) in layer.iter() {
+            if matches!(key.as_str(), "input_path" | "preset") || !TRANSCRIBE_PARAMS.contains(&key.as_str()) {
+                return Err(RuntimeError::InvalidParams(format!("Unknown setting: {key}")).into());
+            }
+            merged.insert(key.clone(), value.clone());
+        }
+    }
+    merged.insert("input_path".to_string(), json!("-"));
+    transcribe_config(&serde_json::Value::Object(merged)).map(|_| ())
+}
+
+/// Where presets are read from: `AER_CONFIG`, else `config.toml` (or an
+/// existing `config.json`) in the per-user config directory.
+pub fn default_config_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os("AER_CONFIG").filter(|path| !path.is_empty()) {
+        return Some(PathBuf::from(path));
+    }
+    let dir = if cfg!(windows) {
+        std::env::var_os("APPDATA").map(|dir| PathBuf::from(dir).join("Subtly"))
+    } else if cfg!(target_os = "macos") {
+        std::env::var_os("HOME").map(|home| PathBuf::from(home).join("Library/Application Support/Subtly"))
+    } else if let Some(dir) = std::env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+        Some(PathBuf::from(dir).join("subtly"))
+    } else {
+        std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config/subtly"))
+    }?;
+    let toml = dir.join("config.toml");
+    let json = dir.join("config.json");
+    Some(if !toml.exists() && json.exists() { json } else { toml })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{restore_env_var, ENV_LOCK};
+
+    const CONFIG: &str = r#"
+[defaults]
+language = "de"
+beam_size = 5
+
+[presets.broadcast]
+max_len_chars = 42
+vad_threshold = 0.5
+
+[presets.fast-draft]
+beam_size = 1
+output_formats = ["txt"]
+
+[lint_profiles.kids]
+max_cps = 12
+"#;
+
+    fn config() -> Config {
+        toml::from_str(CONFIG).unwrap()
+    }
+
+    #[test]
+    fn loads_toml_and_json() {
+        let temp = tempfile::tempdir().unwrap();
+        let toml_path = temp.path().join("config.toml");
+        fs::write(&toml_path, CONFIG).unwrap();
+        assert_eq!(Config::load(&toml_path).unwrap(), config());
+        assert_eq!(config().lint_profiles["kids"]["max_cps"], 12);
+
+        let json_path = temp.path().join("config.json");
+        fs::write(&json_path, r#"{ "presets": { "podcast": { "translate": false } } }"#).unwrap();
+        let loaded = Config::load(&json_path).unwrap();
+        assert!(loaded.defaults.is_empty());
+        assert_eq!(loaded.presets["podcast"]["translate"], false);
+
+        assert_eq!(Config::load(&temp.path().join("missing.toml")).unwrap(), Config::default());
+        let err = Config::load(temp.path()).unwrap_err();
+        assert!(err.to_string().starts_with("Failed to read"), "{err}");
+    }
+
+    #[test]
+    fn reports_unparsable_configs() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("config.toml");
+        fs::write(&path, "[presets.broadcast\n").unwrap();
+        let err = Config::load(&path).unwrap_err();
+        assert_eq!(crate::errors::code_and_data(&err).0, crate::errors::INVALID_CONFIG);
+
+        fs::write(&path, "[profiles.x]\n").unwrap();
+        assert!(Config::load(&path).unwrap_err().to_string().contains("unknown field"));
+
+        let path = temp.path().join("config.json");
+        fs::write(&path, "{").unwrap();
+        assert!(Config::load(&path).unwrap_err().to_string().starts_with("Invalid config"));
+    }
+
+    #[test]
+    fn layers_request_over_preset_over_defaults() {
+        let config = config();
+        let resolved = config
+            .resolve(&json!({ "input_path": "a.mp4", "preset": "fast-draft", "language": "en" }))
+            .unwrap();
+        assert_eq!(
+            resolved,
+            json!({
+                "input_path": "a.mp4",
+                "preset": "fast-draft",
+                "language": "en",
+                "beam_size": 1,
+                "output_formats": ["txt"]
+            })
+        );
+
+        let resolved = config.resolve(&json!({ "input_path": "a.mp4", "preset": null })).unwrap();
+        assert_eq!(resolved["beam_size"], 5);
+        assert_eq!(config.resolve(&json!([1])).unwrap(), json!([1]));
+
+        let err = config.resolve(&json!({ "preset": "nope" })).unwrap_err();
+        assert_eq!(err.to_string(), "Unknown preset: nope");
+        let err = config.resolve(&json!({ "preset": 3 })).unwrap_err();
+        assert_eq!(err.to_string(), "preset must be a string");
+    }
+
+    #[test]
+    fn reports_invalid_presets() {
+        let report = config().report(Some(Path::new("/etc/config.toml")));
+        assert_eq!(report["path"], "/etc/config.toml");
+        assert_eq!(report["valid"], true);
+        assert_eq!(report["defaults"]["params"]["language"], "de");
+        assert_eq!(report["presets"][0]["name"], "broadcast");
+        assert_eq!(report["presets"][1]["params"]["beam_size"], 1);
+
+        let config: Config = toml::from_str(
+            r#"
+[defaults]
+whisper_concurrency = 0
+
+[presets.typo]
+beam = 3
+
+[presets.wrong-type]
+beam_size = "wide"
+
+[presets.pinned]
+input_path = "/media"
+"#,
+        )
+        .unwrap();
+        let report = config.report(None);
+        assert!(report["path"].is_null());
+        assert_eq!(report["valid"], false);
+        assert_eq!(report["defaults"]["error"], "Concurrency limits must be at least 1");
+        let errors = report["presets"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|preset| (preset["name"].as_str().unwrap(), preset["error"].as_str().unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(errors[0], ("pinned", "Unknown setting: input_path"));
+        assert_eq!(errors[1], ("typo", "Unknown setting: beam"));
+        assert!(errors[2].1.starts_with("Invalid transcribe params"), "{}", errors[2].1);
+    }
+
+    #[test]
+    fn finds_the_config_file() {
+        let _guard = ENV_LOCK.lock().unwrap();
+        let original = [
+            ("AER_CONFIG", std::env::var("AER_CONFIG").ok()),
+            ("XDG_CONFIG_HOME", std::env::var("XDG_CONFIG_HOME").ok()),
+            ("HOME", std::env::var("HOME").ok()),
+        ];
+        let temp = tempfile::tempdir().unwrap();
+
+        std::env::set_var("AER_CONFIG", "/etc/subtly.json");
+        assert_eq!(default_config_path(), Some(PathBuf::from("/etc/subtly.json")));
+
+        std::env::remove_var("AER_CONFIG");
+        std::env::set_var("XDG_CONFIG_HOME", temp.path());
+        let dir = temp.path().join("subtly");
+        assert_eq!(default_config_path(), Some(dir.join("config.toml")));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("config.json"), "{}").unwrap();
+        assert_eq!(default_config_path(), Some(dir.join("config.json")));
+        fs::write(dir.join("config.toml"), "").unwrap();
+        assert_eq!(default_config_path(), Some(dir.join("config.toml")));
+
+        std::env::remove_var("XDG_CONFIG_HOME");
+        std::env::set_var("HOME", "/home/me");
+        assert_eq!(
+            default_config_path(),
+            Some(PathBuf::from("/home/me/.config/subtly/config.toml"))
+        );
+        std::env::remove_var("HOME");
+        assert_eq!(default_config_path(), None);
+
+        for (name, value) in original {
+            restore_env_var(name, value);
+        }
+    }
+}
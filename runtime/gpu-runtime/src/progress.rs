@@ -0,0 +1,271 @@
+//! Parsing of ffmpeg and whisper-cli output while the child processes run.
+
+use serde_json::json;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Extract,
+    Transcribe,
+    PostProcess,
+}
+
+impl Stage {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Stage::Extract => "extract",
+            Stage::Transcribe => "transcribe",
+            Stage::PostProcess => "post-process",
+        }
+    }
+}
+
+/// Progress of one stage for one file of a batch. Turns raw percentages into
+/// `progress` event payloads, dropping updates that do not move forward.
+#[derive(Debug)]
+pub struct StageProgress {
+    job_id: u64,
+    file_index: usize,
+    file_count: usize,
+    file: String,
+    stage: Stage,
+    started: Instant,
+    last_percent: Option<f32>,
+}
+
+impl StageProgress {
+    pub fn new(job_id: u64, file_index: usize, file_count: usize, file: &Path, stage: Stage) -> Self {
+        Self {
+            job_id,
+            file_index,
+            file_count,
+            file: file.display().to_string(),
+            stage,
+            started: Instant::now(),
+            last_percent: None,
+        }
+    }
+
+    pub fn job_id(&self) -> u64 {
+        self.job_id
+    }
+
+    pub fn file_index(&self) -> usize {
+        self.file_index
+    }
+
+    pub fn update(&mut self, percent: f32) -> Option<serde_json::Value> {
+        let percent = percent.clamp(0.0, 100.0);
+        if self.last_percent.is_some_and(|last| percent <= last) {
+            return None;
+        }
+        self.last_percent = Some(percent);
+        Some(json!({
+            "job_id": self.job_id,
+            "file_index": self.file_index,
+            "file_count": self.file_count,
+            "file": self.file,
+            "stage": self.stage.as_str(),
+            "percent": percent,
+            "eta_sec": eta_sec(self.started.elapsed(), percent)
+        }))
+    }
+}
+
+/// Linear extrapolation of the remaining time from the elapsed time.
+pub fn eta_sec(elapsed: Duration, percent: f32) -> Option<f64> {
+    if percent <= 0.0 {
+        return None;
+    }
+    let remaining = elapsed.as_secs_f64() * f64::from(100.0 - percent) / f64::from(percent);
+    Some((remaining * 10.0).round() / 10.0)
+}
+
+/// Parses whisper-cli `-pp` lines such as
+/// `whisper_print_progress_callback: progress =  45%`.
+pub fn parse_whisper_progress(line: &str) -> Option<f32> {
+    let (_, rest) = line.split_once("progress =")?;
+    let value = rest.trim().strip_suffix('%')?;
+    value.trim().parse().ok()
+}
+
+/// A segment whisper-cli printed to stdout as soon as it was decoded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SegmentLine {
+    pub start_ms: i64,
+    pub end_ms: i64,
+    pub text: String,
+}
+
+/// Parses transcript lines such as `[00:00:01.000 --> 00:00:03.500]   Hello there.`
+pub fn parse_segment_line(line: &str) -> Option<SegmentLine> {
+    let rest = line.trim_start().strip_prefix('[')?;
+    let (times, text) = rest.split_once(']')?;
+    let (start, end) = times.split_once("-->")?;
+    let text = text.trim();
+    if text.is_empty() {
+        return None;
+    }
+    Some(SegmentLine {
+        start_ms: crate::timestamp_to_ms(start.trim()).ok()?,
+        end_ms: crate::timestamp_to_ms(end.trim()).ok()?,
+        text: text.to_string(),
+    })
+}
+
+/// Accumulates ffmpeg output: the `Duration:` line of the input banner and the
+/// key/value blocks written by `-progress`.
+#[derive(Debug, Default)]
+pub struct FfmpegProgress {
+    duration_us: Option<i64>,
+    out_time_us: Option<i64>,
+}
+
+impl FfmpegProgress {
+    /// Returns the percentage at the end of each `-progress` block, once the
+    /// input duration is known.
+    pub fn feed(&mut self, line: &str) -> Option<f32> {
+        let line = line.trim();
+        if let Some(rest) = line.strip_prefix("Duration:") {
+            let clock = rest.split(',').next().unwrap_or_default();
+            self.duration_us = parse_clock_us(clock.trim());
+            return None;
+        }
+
+        let (key, value) = line.split_once('=')?;
+        match key {
+            // `out_time_ms` is also in microseconds, despite its name.
+            "out_time_us" | "out_time_ms" => {
+                if let Ok(value) = value.parse() {
+                    self.out_time_us = Some(value);
+                }
+                None
+            }
+            "progress" if value == "end" => Some(100.0),
+            "progress" => {
+                let duration = self.duration_us.filter(|duration| *duration > 0)?;
+                let out_time = self.out_time_us?;
+                Some((out_time as f64 / duration as f64 * 100.0).min(100.0) as f32)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Parses `HH:MM:SS.ff` into microseconds.
+fn parse_clock_us(value: &str) -> Option<i64> {
+    let mut parts = value.split(':');
+    let hours: f64 = parts.next()?.parse().ok()?;
+    let minutes: f64 = parts.next()?.parse().ok()?;
+    let seconds: f64 = parts.next()?.parse().ok()?;
+    if parts.next().is_some() {
+        return None;
+    }
+    Some(((hours * 3600.0 + minutes * 60.0 + seconds) * 1_000_000.0).round() as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_stages() {
+        assert_eq!(Stage::Extract.as_str(), "extract");
+        assert_eq!(Stage::Transcribe.as_str(), "transcribe");
+        assert_eq!(Stage::PostProcess.as_str(), "post-process");
+    }
+
+    #[test]
+    fn stage_progress_only_moves_forward() {
+        let mut progress = StageProgress::new(7, 1, 3, Path::new("clip.mp4"), Stage::Transcribe);
+        let first = progress.update(0.0).unwrap();
+        assert_eq!(first["job_id"], 7);
+        assert_eq!(first["file_index"], 1);
+        assert_eq!(first["file_count"], 3);
+        assert_eq!(first["file"], "clip.mp4");
+        assert_eq!(first["stage"], "transcribe");
+        assert_eq!(first["percent"], 0.0);
+        assert!(first["eta_sec"].is_null());
+
+        assert!(progress.update(0.0).is_none());
+        let next = progress.update(150.0).unwrap();
+        assert_eq!(next["percent"], 100.0);
+        assert!(progress.update(50.0).is_none());
+    }
+
+    #[test]
+    fn estimates_remaining_time() {
+        assert_eq!(eta_sec(Duration::from_secs(10), 0.0), None);
+        assert_eq!(eta_sec(Duration::from_secs(10), 25.0), Some(30.0));
+        assert_eq!(eta_sec(Duration::from_secs(10), 100.0), Some(0.0));
+    }
+
+    #[test]
+    fn parses_whisper_progress_lines() {
+        assert_eq!(
+            parse_whisper_progress("whisper_print_progress_callback: progress =  45%"),
+            Some(45.0)
+        );
+        assert_eq!(parse_whisper_progress("progress = 100%"), Some(100.0));
+        assert_eq!(parse_whisper_progress("progress = 45"), None);
+        assert_eq!(parse_whisper_progress("progress = abc%"), None);
+        assert_eq!(parse_whisper_progress("[00:00:00.000 --> 00:00:01.000] hi"), None);
+    }
+
+    #[test]
+    fn parses_segment_lines() {
+        assert_eq!(
+            parse_segment_line("[00:00:01.000 --> 00:00:03.500]   Hello there."),
+            Some(SegmentLine {
+                start_ms: 1000,
+                end_ms: 3500,
+                text: "Hello there.".to_string(),
+            })
+        );
+        assert_eq!(
+            parse_segment_line("[01:00:00,250 --> 01:00:01,000] Comma separated").map(|s| s.start_ms),
+            Some(3_600_250)
+        );
+        assert_eq!(parse_segment_line("[00:00:01.000 --> 00:00:02.000]   "), None);
+        assert_eq!(parse_segment_line("[00:00:01.000 -> 00:00:02.000] arrow"), None);
+        assert_eq!(parse_segment_line("[00:00:bad --> 00:00:02.000] bad"), None);
+        assert_eq!(parse_segment_line("[00:00:01.000 --> 00:00:bad] bad"), None);
+        assert_eq!(parse_segment_line("[unterminated"), None);
+        assert_eq!(parse_segment_line("main: processing 'clip.wav'"), None);
+    }
+
+    #[test]
+    fn parses_ffmpeg_progress_blocks() {
+        let mut progress = FfmpegProgress::default();
+        assert_eq!(progress.feed("out_time_us=1000000"), None);
+        assert_eq!(progress.feed("progress=continue"), None);
+
+        assert_eq!(
+            progress.feed("  Duration: 00:00:04.00, start: 0.000000, bitrate: 128 kb/s"),
+            None
+        );
+        assert_eq!(progress.feed("frame=0"), None);
+        assert_eq!(progress.feed("out_time_ms=1000000"), None);
+        assert_eq!(progress.feed("progress=continue"), Some(25.0));
+        assert_eq!(progress.feed("out_time_us=N/A"), None);
+        assert_eq!(progress.feed("out_time_us=8000000"), None);
+        assert_eq!(progress.feed("progress=continue"), Some(100.0));
+        assert_eq!(progress.feed("progress=end"), Some(100.0));
+        assert_eq!(progress.feed("no separator"), None);
+    }
+
+    #[test]
+    fn ignores_unknown_durations() {
+        let mut progress = FfmpegProgress::default();
+        progress.feed("Duration: N/A, bitrate: N/A");
+        progress.feed("out_time_us=1000");
+        assert_eq!(progress.feed("progress=continue"), None);
+
+        assert_eq!(parse_clock_us("00:01:02.50"), Some(62_500_000));
+        assert_eq!(parse_clock_us("01:02"), None);
+        assert_eq!(parse_clock_us("00:00:01:02"), None);
+        assert_eq!(parse_clock_us("aa:00:00"), None);
+        assert_eq!(parse_clock_us("00:aa:00"), None);
+    }
+}
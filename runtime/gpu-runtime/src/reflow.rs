@@ -0,0 +1,468 @@
+//! Broadcast-style layout. Decoded segments are re-cut into cues of at most
+//! `max_lines` balanced lines, preferring breaks at sentence and clause ends,
+//! pauses and before conjunctions, then retimed for reading speed, minimum
+//! duration and the gap between cues.
+
+use crate::transcript::{Segment, Word};
+use serde::Serialize;
+
+/// Layout constraints for one `transcribe` call.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Layout {
+    pub max_line_chars: usize,
+    pub max_lines: usize,
+    /// Reading speed in characters per second, line breaks not counted.
+    pub max_cps: f64,
+    pub min_duration_ms: i64,
+    pub max_duration_ms: i64,
+    pub min_gap_ms: i64,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Self {
+            max_line_chars: 42,
+            max_lines: 2,
+            max_cps: 17.0,
+            min_duration_ms: 1000,
+            max_duration_ms: 7000,
+            min_gap_ms: 80,
+        }
+    }
+}
+
+/// A pause between words this long is a natural place for a new cue.
+const PAUSE_MS: i64 = 300;
+
+/// Words a line should not end with, per language: articles, prepositions,
+/// conjunctions and possessives.
+const DANGLING: &[(&str, &[&str])] = &[
+    (
+        "en",
+        &[
+            "a", "an", "the", "of", "to", "in", "on", "at", "by", "for", "with", "from", "and", "or", "but", "my",
+            "your", "his", "her", "our", "their", "its", "this", "that",
+        ],
+    ),
+    (
+        "de",
+        &[
+            "der", "die", "das", "den", "dem", "des", "ein", "eine", "einen", "einem", "einer", "eines", "zu", "von",
+            "mit", "für", "auf", "an", "in", "im", "am", "bei", "aus", "nach", "und", "oder", "aber", "mein", "dein",
+            "sein", "unser",
+        ],
+    ),
+    (
+        "es",
+        &[
+            "el", "la", "los", "las", "un", "una", "unos", "unas", "de", "del", "a", "al", "en", "con", "por", "para",
+            "y", "o", "pero", "mi", "tu", "su", "este", "esta",
+        ],
+    ),
+    (
+        "fr",
+        &[
+            "le", "la", "les", "un", "une", "des", "du", "de", "à", "au", "aux", "en", "dans", "sur", "par", "pour",
+            "avec", "et", "ou", "mais", "mon", "ma", "mes", "ton", "ta", "tes", "son", "sa", "ses", "ce", "cette",
+        ],
+    ),
+];
+
+/// Words a line or cue may start with, per language: conjunctions and
+/// relative pronouns.
+const LEADING: &[(&str, &[&str])] = &[
+    (
+        "en",
+        &["and", "but", "or", "so", "because", "that", "which", "who", "when", "where", "if", "while", "although"],
+    ),
+    (
+        "de",
+        &["und", "aber", "oder", "denn", "weil", "dass", "wenn", "als", "ob", "während", "obwohl", "sondern"],
+    ),
+    (
+        "es",
+        &["y", "pero", "o", "porque", "que", "quien", "cuando", "donde", "si", "aunque", "mientras"],
+    ),
+    (
+        "fr",
+        &["et", "mais", "ou", "donc", "car", "parce", "que", "qui", "quand", "où", "si", "lorsque", "pendant"],
+    ),
+];
+
+/// The [`DANGLING`] and [`LEADING`] lists for one language. Languages without
+/// lists only break at punctuation and pauses.
+#[derive(Debug, Clone, Copy, Default)]
+struct WordLists {
+    dangling: &'static [&'static str],
+    leading: &'static [&'static str],
+}
+
+impl WordLists {
+    fn for_language(language: Option<&str>) -> Self {
+        let find = |table: &'static [(&str, &'static [&'static str])]| {
+            table
+                .iter()
+                .find(|(code, _)| Some(*code) == language)
+                .map_or(&[][..], |(_, words)| *words)
+        };
+        Self {
+            dangling: find(DANGLING),
+            leading: find(LEADING),
+        }
+    }
+}
+
+/// Re-cuts `segments`; `language` picks the word lists for line and cue
+/// breaks.
+pub fn reflow(segments: Vec<Segment>, layout: &Layout, language: Option<&str>) -> Vec<Segment> {
+    let lists = WordLists::for_language(language);
+    let mut cues = Vec::new();
+    for segment in segments {
+        let words = if segment.words.is_empty() {
+            interpolate(&segment)
+        } else {
+            segment.words
+        };
+        let words = words
+            .into_iter()
+            .filter(|word| !word.text.trim().is_empty())
+            .collect::<Vec<_>>();
+        cues.extend(split(&words, layout, &lists));
+    }
+    retime(&mut cues, layout);
+    cues
+}
+
+/// Words spread over the segment by length, for segments whisper-cli gave
+/// no token timings for.
+fn interpolate(segment: &Segment) -> Vec<Word> {
+    let texts = segment.text.split_whitespace().collect::<Vec<_>>();
+    let total = texts.iter().map(|text| text.chars().count()).sum::<usize>().max(1) as i64;
+    let span = segment.end_ms - segment.start_ms;
+    let mut done = 0;
+    texts
+        .into_iter()
+        .map(|text| {
+            let start_ms = segment.start_ms + span * done / total;
+            done += text.chars().count() as i64;
+            Word {
+                text: format!(" {text}"),
+                start_ms,
+                end_ms: segment.start_ms + span * done / total,
+                tokens: Vec::new(),
+            }
+        })
+        .collect()
+}
+
+/// Cuts `words` into cues that fit the layout, choosing each cut by how
+/// natural the boundary is and how full the cue gets.
+fn split(words: &[Word], layout: &Layout, lists: &WordLists) -> Vec<Segment> {
+    let capacity = (layout.max_line_chars * layout.max_lines) as f64;
+    let fits = |words: &[Word]| {
+        words.len() == 1
+            || (words[words.len() - 1].end_ms - words[0].start_ms <= layout.max_duration_ms
+                && lines(words, layout, lists).is_some())
+    };
+    let mut cues = Vec::new();
+    let mut rest = words;
+    while !rest.is_empty() {
+        if fits(rest) {
+            cues.push(cue(rest, layout, lists));
+            break;
+        }
+        let longest = (1..rest.len()).rev().find(|&end| fits(&rest[..end])).unwrap_or(1);
+        let end = (1..=longest)
+            .map(|end| {
+                let fill = text_len(&rest[..end]) as f64 / capacity;
+                (end, boundary(&rest[end - 1], &rest[end], lists) + fill.min(1.0))
+            })
+            .fold((longest, f64::MIN), |best, candidate| if candidate.1 > best.1 { candidate } else { best })
+            .0;
+        cues.push(cue(&rest[..end], layout, lists));
+        rest = &rest[end..];
+    }
+    cues
+}
+
+/// How good a cue boundary between `before` and `after` is.
+fn boundary(before: &Word, after: &Word, lists: &WordLists) -> f64 {
+    let text = before.text.trim();
+    let mut score: f64 = if text.ends_with(['.', '?', '!', '…']) {
+        3.0
+    } else if text.ends_with([',', ';', ':', '-', '–', '—']) {
+        2.0
+    } else if is_one_of(after, lists.leading) {
+        1.0
+    } else if is_one_of(before, lists.dangling) {
+        -1.0
+    } else {
+        0.0
+    };
+    if after.start_ms - before.end_ms >= PAUSE_MS {
+        score += 1.5;
+    }
+    score
+}
+
+fn is_one_of(word: &Word, list: &[&str]) -> bool {
+    list.contains(&word.text.trim().to_lowercase().as_str())
+}
+
+fn cue(words: &[Word], layout: &Layout, lists: &WordLists) -> Segment {
+    let lines = lines(words, layout, lists).unwrap_or_else(|| vec![join(words)]);
+    Segment {
+        start_ms: words[0].start_ms,
+        end_ms: words[words.len() - 1].end_ms,
+        text: lines.join("\n"),
+        words: words.to_vec(),
+    }
+}
+
+fn join(words: &[Word]) -> String {
+    words.iter().map(|word| word.text.as_str()).collect::<String>().trim().to_string()
+}
+
+fn text_len(words: &[Word]) -> usize {
+    join(words).chars().count()
+}
+
+/// The fewest lines of at most `max_line_chars` that hold `words`, breaking
+/// so that lines are balanced and do not end on articles or prepositions.
+/// A single word longer than a line gets a line of its own.
+fn lines(words: &[Word], layout: &Layout, lists: &WordLists) -> Option<Vec<String>> {
+    let n = words.len();
+    let line_cost = |from: usize, to: usize| {
+        let len = text_len(&words[from..to]);
+        if len > layout.max_line_chars && to - from > 1 {
+            return None;
+        }
+        let penalty = match to {
+            to if to == n => 0.0,
+            to => match boundary(&words[to - 1], &words[to], lists) {
+                score if score >= 2.0 => 0.0,
+                score if score >= 1.0 => 20.0,
+                score if score < 0.0 => 300.0,
+                _ => 100.0,
+            },
+        };
+        Some((len * len) as f64 + penalty)
+    };
+    for count in 1..=layout.max_lines.min(n) {
+        // best[k][j]: cheapest way to put words[..j] on k lines, with the
+        // start of the last line.
+        let mut best = vec![vec![None::<(f64, usize)>; n + 1]; count + 1];
+        best[0][0] = Some((0.0, 0));
+        for k in 1..=count {
+            for j in k..=n {
+                best[k][j] = (k - 1..j)
+                    .filter_map(|i| {
+                        let (cost, _) = best[k - 1][i]?;
+                        Some((cost + line_cost(i, j)?, i))
+                    })
+                    .min_by(|a, b| a.0.total_cmp(&b.0));
+            }
+        }
+        if best[count][n].is_some() {
+            let mut breaks = vec![n];
+            for k in (1..=count).rev() {
+                let (_, start) = best[k][breaks[breaks.len() - 1]].unwrap();
+                breaks.push(start);
+            }
+            breaks.reverse();
+            return Some(breaks.windows(2).map(|range| join(&words[range[0]..range[1]])).collect());
+        }
+    }
+    None
+}
+
+/// Extends cues that are too short or too fast to read into the following
+/// silence and keeps `min_gap_ms` between cues.
+fn retime(cues: &mut [Segment], layout: &Layout) {
+    for index in 0..cues.len() {
+        let limit = cues
+            .get(index + 1)
+            .map_or(i64::MAX, |next| next.start_ms - layout.min_gap_ms);
+        let cue = &mut cues[index];
+        let chars = cue.text.chars().filter(|c| *c != '\n').count() as f64;
+        let reading_ms = (chars * 1000.0 / layout.max_cps).ceil() as i64;
+        let wanted = reading_ms.max(layout.min_duration_ms).min(layout.max_duration_ms);
+        if cue.end_ms - cue.start_ms < wanted {
+            cue.end_ms = (cue.start_ms + wanted).min(limit).max(cue.end_ms);
+        }
+        cue.end_ms = cue.end_ms.min(limit).max(cue.start_ms + 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transcript::test_texts;
+
+    fn words(spec: &[(&str, i64, i64)]) -> Vec<Word> {
+        spec.iter()
+            .map(|&(text, start_ms, end_ms)| Word {
+                text: format!(" {text}"),
+                start_ms,
+                end_ms,
+                tokens: Vec::new(),
+            })
+            .collect()
+    }
+
+    fn segment(spec: &[(&str, i64, i64)]) -> Segment {
+        let words = words(spec);
+        Segment {
+            start_ms: words[0].start_ms,
+            end_ms: words[words.len() - 1].end_ms,
+            text: join(&words),
+            words,
+        }
+    }
+
+    const EN: Option<&str> = Some("en");
+
+    /// One word every 300 ms.
+    fn spoken(text: &str, from_ms: i64) -> Vec<(&str, i64, i64)> {
+        text.split(' ')
+            .enumerate()
+            .map(|(index, word)| (word, from_ms + index as i64 * 300, from_ms + index as i64 * 300 + 280))
+            .collect()
+    }
+
+    #[test]
+    fn balances_lines_at_natural_breaks() {
+        let layout = Layout {
+            max_line_chars: 30,
+            ..Layout::default()
+        };
+        let cues = reflow(
+            vec![segment(&spoken("I went to the market and bought fresh bread", 0))],
+            &layout,
+            EN,
+        );
+        assert_eq!(test_texts(&cues), ["I went to the market\nand bought fresh bread"]);
+        // Too long for two lines: the cue is cut before "and" instead.
+        let narrow = Layout {
+            max_line_chars: 20,
+            ..layout.clone()
+        };
+        let cues = reflow(
+            vec![segment(&spoken("I went to the market and bought fresh bread", 0))],
+            &narrow,
+            EN,
+        );
+        assert_eq!(test_texts(&cues), ["I went to the market", "and bought\nfresh bread"]);
+        let layout = narrow;
+        let en = WordLists::for_language(EN);
+        // A short line stays whole, and so does a word longer than a line.
+        assert_eq!(lines(&words(&spoken("Hi there", 0)), &layout, &en).unwrap(), ["Hi there"]);
+        let long = words(&[("Supercalifragilistic", 0, 500), ("indeed", 500, 900)]);
+        assert_eq!(lines(&long, &layout, &en).unwrap(), ["Supercalifragilistic", "indeed"]);
+        assert_eq!(lines(&long[..1], &Layout { max_line_chars: 5, ..layout.clone() }, &en).unwrap().len(), 1);
+        let three = words(&spoken("aaaa bbbb cccc", 0));
+        assert_eq!(lines(&three, &Layout { max_line_chars: 4, ..layout }, &en), None);
+    }
+
+    #[test]
+    fn cuts_cues_at_sentences_and_pauses() {
+        let layout = Layout {
+            max_line_chars: 16,
+            max_lines: 1,
+            ..Layout::default()
+        };
+        let mut spec = spoken("Yes. I think we should go now", 0);
+        let cues = reflow(vec![segment(&spec)], &layout, EN);
+        assert_eq!(test_texts(&cues), ["Yes.", "I think we", "should go now"]);
+
+        // A pause beats a plain word boundary; dangling words are avoided.
+        spec = spoken("one two three four five", 0);
+        spec[3].1 += 1000;
+        spec[3].2 += 1000;
+        spec[4].1 += 1000;
+        spec[4].2 += 1000;
+        let cues = reflow(vec![segment(&spec)], &layout, EN);
+        assert_eq!(test_texts(&cues), ["one two three", "four five"]);
+        let cues = reflow(vec![segment(&spoken("look at the cat and dog", 0))], &layout, EN);
+        assert_eq!(test_texts(&cues), ["look at the cat", "and dog"]);
+
+        // Cues never run longer than max_duration_ms.
+        let slow = Layout {
+            max_duration_ms: 1000,
+            ..Layout::default()
+        };
+        let cues = reflow(vec![segment(&spoken("a b c d e f g", 0))], &slow, EN);
+        assert!(cues.iter().all(|cue| cue.end_ms - cue.start_ms <= 1000), "{cues:?}");
+        assert_eq!(cues.len(), 3);
+        let en = WordLists::for_language(EN);
+        assert_eq!(boundary(&words(&[("well,", 0, 1)])[0], &words(&[("so", 1, 2)])[0], &en), 2.0);
+    }
+
+    #[test]
+    fn retimes_for_reading_speed_and_gaps() {
+        let layout = Layout::default();
+        let cues = reflow(
+            vec![
+                segment(&[("Hello", 0, 200)]),
+                segment(&[("This", 900, 1000), ("is", 1000, 1100), ("a", 1100, 1200), ("longer", 1200, 1300), ("line.", 1300, 1400)]),
+                segment(&[("Next.", 2000, 3000)]),
+            ],
+            &layout,
+            EN,
+        );
+        // "Hello" gets the minimum duration but keeps the gap to the next cue.
+        assert_eq!((cues[0].start_ms, cues[0].end_ms), (0, 820));
+        // 22 characters at 17 cps want 1295 ms; the next cue starts at 2000.
+        assert_eq!((cues[1].start_ms, cues[1].end_ms), (900, 1920));
+        assert_eq!((cues[2].start_ms, cues[2].end_ms), (2000, 3000));
+
+        // Overlapping input still leaves the gap and a positive duration.
+        let mut cues = vec![segment(&[("a", 0, 500)]), segment(&[("b", 40, 600)])];
+        retime(&mut cues, &layout);
+        assert_eq!((cues[0].start_ms, cues[0].end_ms), (0, 1));
+        assert_eq!(cues[1].end_ms, 1040);
+    }
+
+    #[test]
+    fn spreads_untimed_segments_over_their_span() {
+        let segment = Segment {
+            start_ms: 0,
+            end_ms: 9000,
+            text: " One, two three. Four five six seven eight nine ten eleven twelve".to_string(),
+            words: Vec::new(),
+        };
+        let layout = Layout {
+            max_line_chars: 20,
+            ..Layout::default()
+        };
+        let cues = reflow(vec![segment.clone(), Segment { text: " ".to_string(), ..segment }], &layout, EN);
+        assert_eq!(test_texts(&cues), ["One, two three.", "Four five six seven\neight nine ten", "eleven twelve"]);
+        assert_eq!(cues[0].start_ms, 0);
+        assert_eq!(cues[2].words[1].end_ms, 9000);
+        assert_eq!(cues[1].confidence(), None);
+    }
+
+    #[test]
+    fn picks_word_lists_by_language() {
+        let layout = Layout {
+            max_line_chars: 20,
+            ..Layout::default()
+        };
+        let german = spoken("Ich bin in die Stadt gegangen und habe Brot gekauft", 0);
+        let cues = reflow(vec![segment(&german)], &layout, Some("de"));
+        assert_eq!(test_texts(&cues), ["Ich bin in die Stadt\ngegangen", "und habe\nBrot gekauft"]);
+        // Without lists nothing favours starting the cue at "und".
+        let cues = reflow(vec![segment(&german)], &layout, None);
+        assert_eq!(test_texts(&cues), ["Ich bin in die Stadt\ngegangen und habe", "Brot gekauft"]);
+
+        // English lists do not apply to other languages.
+        let french = words(&spoken("le chat and", 0));
+        for language in [Some("fr"), Some("xx"), None] {
+            let lists = WordLists::for_language(language);
+            assert_eq!(boundary(&french[1], &french[2], &lists), 0.0);
+        }
+        let lists = WordLists::for_language(Some("fr"));
+        assert_eq!(boundary(&french[0], &french[1], &lists), -1.0);
+        assert_eq!(boundary(&french[0], &french[1], &WordLists::for_language(None)), 0.0);
+    }
+}
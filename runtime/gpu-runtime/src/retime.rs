@@ -0,0 +1,220 @@
+//! Moves subtitle timings by a linear map: a constant offset (a new intro),
+//! a stretch fixed by two anchor points (drift), or a framerate conversion
+//! (e.g. a 23.976 fps master shown at 25 fps), optionally only for the cues
+//! in a time range. Works on SRT/VTT documents and on segments read back from
+//! the other timed formats.
+
+use crate::errors::RuntimeError;
+use crate::subtitles::{Block, Document};
+use crate::transcript::{Format, Segment};
+use anyhow::Result;
+
+/// `ms * scale + offset_ms`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    pub scale: f64,
+    pub offset_ms: f64,
+}
+
+impl Transform {
+    pub fn shift(offset_ms: i64) -> Self {
+        Self {
+            scale: 1.0,
+            offset_ms: offset_ms as f64,
+        }
+    }
+
+    /// The stretch that moves `first.0` to `first.1` and `second.0` to
+    /// `second.1`.
+    pub fn anchors(first: (i64, i64), second: (i64, i64)) -> Result<Self> {
+        if first.0 == second.0 {
+            return Err(RuntimeError::InvalidParams("Anchors must be at different times".to_string()).into());
+        }
+        let scale = (second.1 - first.1) as f64 / (second.0 - first.0) as f64;
+        if scale <= 0.0 {
+            return Err(RuntimeError::InvalidParams("Anchors must keep cues in order".to_string()).into());
+        }
+        Ok(Self {
+            scale,
+            offset_ms: first.1 as f64 - scale * first.0 as f64,
+        })
+    }
+
+    /// Timings for the same frames played at `to_fps` instead of `from_fps`.
+    pub fn fps(from_fps: f64, to_fps: f64) -> Result<Self> {
+        if from_fps <= 0.0 || to_fps <= 0.0 {
+            return Err(RuntimeError::InvalidParams("Framerates must be greater than 0".to_string()).into());
+        }
+        Ok(Self {
+            scale: from_fps / to_fps,
+            offset_ms: 0.0,
+        })
+    }
+
+    /// This transform followed by `next`.
+    pub fn then(self, next: Self) -> Self {
+        Self {
+            scale: self.scale * next.scale,
+            offset_ms: self.offset_ms * next.scale + next.offset_ms,
+        }
+    }
+
+    pub fn apply(&self, ms: i64) -> i64 {
+        (ms as f64 * self.scale + self.offset_ms).round() as i64
+    }
+}
+
+/// What [`retime`] did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Outcome {
+    pub retimed: usize,
+    /// Cues moved entirely before 0.
+    pub removed: usize,
+}
+
+/// Applies `transform` to the cues starting in `range` (all cues when
+/// `None`). Cues moved before 0 are cut at 0, or dropped when nothing is left
+/// of them; SRT cues are then renumbered.
+pub fn retime(document: &mut Document, transform: &Transform, range: Option<(i64, i64)>) -> Outcome {
+    let mut outcome = Outcome { retimed: 0, removed: 0 };
+    document.blocks.retain_mut(|block| {
+        let Block::Cue(cue) = block else {
+            return true;
+        };
+        outcome.keep(move_span(&mut cue.start_ms, &mut cue.end_ms, transform, range))
+    });
+    if outcome.removed > 0 && document.format == Format::Srt {
+        for (index, cue) in document.cues_mut().enumerate() {
+            cue.id = Some((index + 1).to_string());
+        }
+    }
+    outcome
+}
+
+/// [`retime`] for segments, moving their word and token timings along.
+pub fn retime_segments(segments: &mut Vec<Segment>, transform: &Transform, range: Option<(i64, i64)>) -> Outcome {
+    let mut outcome = Outcome { retimed: 0, removed: 0 };
+    segments.retain_mut(|segment| {
+        let moved = move_span(&mut segment.start_ms, &mut segment.end_ms, transform, range);
+        if moved == Some(true) {
+            for word in &mut segment.words {
+                word.start_ms = transform.apply(word.start_ms).max(0);
+                word.end_ms = transform.apply(word.end_ms).max(0);
+                for token in &mut word.tokens {
+                    token.start_ms = transform.apply(token.start_ms).max(0);
+                    token.end_ms = transform.apply(token.end_ms).max(0);
+                }
+            }
+        }
+        outcome.keep(moved)
+    });
+    outcome
+}
+
+/// Applies `transform` to a span starting in `range`: `None` when it is out
+/// of range, else whether anything is left of it after 0.
+fn move_span(start_ms: &mut i64, end_ms: &mut i64, transform: &Transform, range: Option<(i64, i64)>) -> Option<bool> {
+    if range.is_some_and(|(start, end)| *start_ms < start || *start_ms >= end) {
+        return None;
+    }
+    *start_ms = transform.apply(*start_ms).max(0);
+    *end_ms = transform.apply(*end_ms);
+    Some(*end_ms > 0)
+}
+
+impl Outcome {
+    /// Counts a [`move_span`] result; `false` when the item is to be dropped.
+    fn keep(&mut self, moved: Option<bool>) -> bool {
+        match moved {
+            Some(true) => self.retimed += 1,
+            Some(false) => self.removed += 1,
+            None => {}
+        }
+        moved != Some(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spans(document: &Document) -> Vec<(i64, i64)> {
+        document.cues().map(|cue| (cue.start_ms, cue.end_ms)).collect()
+    }
+
+    const SRT: &str = "1\n00:00:01,000 --> 00:00:02,000\nOne\n\n\
+                       2\n00:00:10,000 --> 00:00:12,000\nTwo\n\n\
+                       3\n00:01:00,000 --> 00:01:02,500\nThree\n";
+
+    #[test]
+    fn builds_linear_maps() {
+        assert_eq!(Transform::shift(-500).apply(1000), 500);
+        let stretch = Transform::anchors((1000, 1500), (11_000, 11_000)).unwrap();
+        assert_eq!((stretch.apply(1000), stretch.apply(11_000), stretch.apply(6000)), (1500, 11_000, 6250));
+        let pal = Transform::fps(23.976, 25.0).unwrap();
+        assert_eq!(pal.apply(3_600_000), 3_452_544);
+        assert_eq!(Transform::fps(25.0, 23.976).unwrap().apply(3_452_544), 3_600_000);
+        let both = pal.then(Transform::shift(2000));
+        assert_eq!(both.apply(3_600_000), 3_454_544);
+        assert_eq!(Transform::shift(2000).then(pal).apply(0), 1918);
+
+        for err in [
+            Transform::anchors((1000, 0), (1000, 5)).unwrap_err(),
+            Transform::anchors((1000, 5000), (2000, 4000)).unwrap_err(),
+            Transform::fps(0.0, 25.0).unwrap_err(),
+        ] {
+            assert_eq!(crate::errors::code_and_data(&err).0, crate::errors::INVALID_PARAMS);
+        }
+    }
+
+    #[test]
+    fn retimes_cues_in_range() {
+        let mut document = Document::parse(SRT, Format::Srt);
+        let outcome = retime(&mut document, &Transform::shift(5000), Some((5000, 60_000)));
+        assert_eq!(outcome, Outcome { retimed: 1, removed: 0 });
+        assert_eq!(spans(&document), [(1000, 2000), (15_000, 17_000), (60_000, 62_500)]);
+
+        let outcome = retime(&mut document, &Transform::shift(-1500), None);
+        assert_eq!(outcome, Outcome { retimed: 3, removed: 0 });
+        assert_eq!(spans(&document)[0], (0, 500));
+    }
+
+    #[test]
+    fn drops_cues_moved_before_zero_and_renumbers() {
+        let text = format!("WEBVTT\n\n{}", SRT.replace(',', "."));
+        let mut document = Document::parse(&text, Format::Vtt);
+        let outcome = retime(&mut document, &Transform::shift(-10_000), None);
+        assert_eq!(outcome, Outcome { retimed: 2, removed: 1 });
+        assert_eq!(
+            document.render(),
+            "WEBVTT\n\n2\n00:00:00.000 --> 00:00:02.000\nTwo\n\n3\n00:00:50.000 --> 00:00:52.500\nThree\n"
+        );
+
+        let mut document = Document::parse(SRT, Format::Srt);
+        retime(&mut document, &Transform::shift(-10_000), None);
+        let ids = document.cues().map(|cue| cue.id.clone().unwrap()).collect::<Vec<_>>();
+        assert_eq!(ids, ["1", "2"]);
+    }
+
+    #[test]
+    fn retimes_segments_with_their_words() {
+        let mut segments = crate::transcript::parse(
+            r#"{"transcription": [
+                { "offsets": { "from": 500, "to": 900 }, "text": " Gone" },
+                { "offsets": { "from": 1000, "to": 2000 }, "text": " Hi there",
+                  "tokens": [
+                    { "text": " Hi", "offsets": { "from": 1000, "to": 1400 }, "p": 0.9 },
+                    { "text": " there", "offsets": { "from": 1400, "to": 2000 }, "p": 0.8 } ] },
+                { "offsets": { "from": 9000, "to": 9500 }, "text": " Later" }
+            ]}"#,
+        )
+        .unwrap()
+        .segments;
+        let outcome = retime_segments(&mut segments, &Transform::shift(-1200), Some((0, 5000)));
+        assert_eq!(outcome, Outcome { retimed: 1, removed: 1 });
+        assert_eq!(crate::transcript::test_spans(&segments), [(0, 800), (9000, 9500)]);
+        let words = &segments[0].words;
+        assert_eq!((words[0].start_ms, words[0].end_ms, words[1].start_ms), (0, 200, 200));
+        assert_eq!(words[1].tokens[0].end_ms, 800);
+    }
+}
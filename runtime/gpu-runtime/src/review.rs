@@ -0,0 +1,260 @@
+//! QC report of the lines worth a second look: low whisper confidence,
+//! suspected hallucinations and text where the VAD hears little speech.
+//! Written as `<name>.review.json` and `<name>.review.md` next to the
+//! subtitles.
+
+use crate::hallucination;
+use crate::transcript::{self, Segment};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+pub fn paths_for(output_base: &Path) -> [PathBuf; 2] {
+    [
+        output_base.with_extension("review.json"),
+        output_base.with_extension("review.md"),
+    ]
+}
+
+#[derive(Debug, Serialize)]
+pub struct Report {
+    file: String,
+    confidence_threshold: f64,
+    /// `vad`, or `energy` when asked to go by loudness.
+    speech_source: &'static str,
+    speech_threshold: f64,
+    segment_count: usize,
+    flagged: Vec<Flagged>,
+}
+
+#[derive(Debug, Serialize)]
+struct Flagged {
+    start_ms: i64,
+    end_ms: i64,
+    /// Where to seek to, as `HH:MM:SS.mmm`.
+    timestamp: String,
+    text: String,
+    issues: Vec<Issue>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum Issue {
+    /// Average token probability below the threshold, with the words that
+    /// are themselves below it.
+    LowConfidence {
+        avg_confidence: f64,
+        min_confidence: f64,
+        words: Vec<String>,
+    },
+    Hallucination { reason: &'static str },
+    /// Share of the segment inside the speech regions, below the threshold.
+    LowSpeech { speech_ratio: f64 },
+}
+
+/// Where the speech regions come from and how much of a segment they have to
+/// cover.
+pub struct SpeechCheck<'a> {
+    pub source: &'static str,
+    pub regions: &'a [(i64, i64)],
+    pub threshold: f64,
+}
+
+impl Report {
+    pub fn build(
+        file: &Path,
+        segments: &[Segment],
+        language: Option<&str>,
+        confidence_threshold: f64,
+        speech: &SpeechCheck,
+    ) -> Self {
+        let round = |value: f64| (value * 1000.0).round() / 1000.0;
+        let flagged = segments
+            .iter()
+            .filter_map(|segment| {
+                let mut issues = Vec::new();
+                if let Some((average, min)) = segment.confidence().filter(|(average, _)| *average < confidence_threshold) {
+                    issues.push(Issue::LowConfidence {
+                        avg_confidence: round(average),
+                        min_confidence: round(min),
+                        words: segment
+                            .words
+                            .iter()
+                            .filter(|word| word.confidence() < confidence_threshold)
+                            .map(|word| word.text.trim().to_string())
+                            .collect(),
+                    });
+                }
+                if let Some(reason) = hallucination::suspect(&segment.text, language) {
+                    issues.push(Issue::Hallucination { reason });
+                }
+                let speech_ratio = covered(speech.regions, segment.start_ms, segment.end_ms);
+                if speech_ratio < speech.threshold {
+                    issues.push(Issue::LowSpeech {
+                        speech_ratio: round(speech_ratio),
+                    });
+                }
+                (!issues.is_empty()).then(|| Flagged {
+                    start_ms: segment.start_ms,
+                    end_ms: segment.end_ms,
+                    timestamp: transcript::timestamp(segment.start_ms, '.'),
+                    text: segment.text.trim().to_string(),
+                    issues,
+                })
+            })
+            .collect();
+        Self {
+            file: file.display().to_string(),
+            confidence_threshold,
+            speech_source: speech.source,
+            speech_threshold: speech.threshold,
+            segment_count: segments.len(),
+            flagged,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap() + "\n"
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut out = format!(
+            "# Review: {}\n\n{} of {} segments flagged (confidence below {}, speech below {} by {}).\n",
+            self.file,
+            self.flagged.len(),
+            self.segment_count,
+            self.confidence_threshold,
+            self.speech_threshold,
+            self.speech_source
+        );
+        if self.flagged.is_empty() {
+            return out;
+        }
+        out.push_str("\n| Time | Text | Issues |\n| --- | --- | --- |\n");
+        for flagged in &self.flagged {
+            let issues = flagged
+                .issues
+                .iter()
+                .map(|issue| match issue {
+                    Issue::LowConfidence {
+                        avg_confidence,
+                        min_confidence,
+                        words,
+                    } => format!(
+                        "low confidence (avg {avg_confidence}, min {min_confidence}): {}",
+                        words.join(", ")
+                    ),
+                    Issue::Hallucination { reason } => format!("suspected hallucination ({reason})"),
+                    Issue::LowSpeech { speech_ratio } => format!("little speech ({speech_ratio})"),
+                })
+                .collect::<Vec<_>>();
+            out.push_str(&format!(
+                "| {} | {} | {} |\n",
+                flagged.timestamp,
+                escape(&flagged.text),
+                escape(&issues.join("; "))
+            ));
+        }
+        out
+    }
+}
+
+/// Share of `start_ms..end_ms` inside `regions`; `0` for an empty span.
+fn covered(regions: &[(i64, i64)], start_ms: i64, end_ms: i64) -> f64 {
+    if end_ms <= start_ms {
+        return 0.0;
+    }
+    let overlap = regions
+        .iter()
+        .map(|&(start, end)| (end.min(end_ms) - start.max(start_ms)).max(0))
+        .sum::<i64>();
+    overlap as f64 / (end_ms - start_ms) as f64
+}
+
+fn escape(text: &str) -> String {
+    text.replace('|', "\\|").replace('\n', "<br>")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRANSCRIPT: &str = r#"{"transcription": [
+        { "offsets": { "from": 0, "to": 1000 }, "text": " Clear line.",
+          "tokens": [
+            { "text": " Clear", "offsets": { "from": 0, "to": 500 }, "p": 0.95 },
+            { "text": " line.", "offsets": { "from": 500, "to": 1000 }, "p": 0.9 } ] },
+        { "offsets": { "from": 1000, "to": 2000 }, "text": " Mumbled | words",
+          "tokens": [
+            { "text": " Mumbled", "offsets": { "from": 1000, "to": 1500 }, "p": 0.2 },
+            { "text": " |", "offsets": { "from": 1500, "to": 1600 }, "p": 0.9 },
+            { "text": " words", "offsets": { "from": 1600, "to": 2000 }, "p": 0.3 } ] },
+        { "offsets": { "from": 2000, "to": 3000 }, "text": " Thanks for watching!" }
+    ]}"#;
+
+    #[test]
+    fn flags_doubtful_segments() {
+        let segments = transcript::parse(TRANSCRIPT).unwrap().segments;
+        let speech = SpeechCheck {
+            source: "vad",
+            regions: &[(0, 1980)],
+            threshold: 0.35,
+        };
+        let report = Report::build(Path::new("clip.mp4"), &segments, Some("en"), 0.6, &speech);
+        assert_eq!(report.segment_count, 3);
+        assert_eq!(report.flagged.len(), 2);
+        assert_eq!(
+            report.flagged[0].issues,
+            [Issue::LowConfidence {
+                avg_confidence: 0.467,
+                min_confidence: 0.2,
+                words: vec!["Mumbled".to_string(), "words".to_string()]
+            }]
+        );
+        assert_eq!(report.flagged[1].timestamp, "00:00:02.000");
+        assert_eq!(
+            report.flagged[1].issues,
+            [
+                Issue::Hallucination { reason: "phrase" },
+                Issue::LowSpeech { speech_ratio: 0.0 }
+            ]
+        );
+
+        let json: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+        assert_eq!(json["flagged"][0]["issues"][0]["kind"], "low_confidence");
+        assert_eq!(json["flagged"][1]["issues"][1]["kind"], "low_speech");
+        assert_eq!(json["speech_source"], "vad");
+        assert_eq!(json["file"], "clip.mp4");
+
+        let markdown = report.to_markdown();
+        assert!(markdown.starts_with("# Review: clip.mp4\n\n2 of 3 segments flagged"), "{markdown}");
+        assert!(
+            markdown.contains("| 00:00:01.000 | Mumbled \\| words | low confidence (avg 0.467, min 0.2): Mumbled, words |"),
+            "{markdown}"
+        );
+        assert!(
+            markdown.contains("| suspected hallucination (phrase); little speech (0) |"),
+            "{markdown}"
+        );
+    }
+
+    #[test]
+    fn reports_clean_transcripts() {
+        let segments = transcript::parse(TRANSCRIPT).unwrap().segments;
+        let speech = SpeechCheck {
+            source: "energy",
+            regions: &[(0, 600), (600, 3000)],
+            threshold: 0.35,
+        };
+        let report = Report::build(Path::new("clip.mp4"), &segments[..1], None, 0.6, &speech);
+        assert!(report.flagged.is_empty());
+        assert_eq!(
+            report.to_markdown(),
+            "# Review: clip.mp4\n\n0 of 1 segments flagged (confidence below 0.6, speech below 0.35 by energy).\n"
+        );
+        assert_eq!(covered(&[(500, 750), (900, 2000)], 0, 1000), 0.35);
+        assert_eq!(covered(&[(0, 1000)], 500, 500), 0.0);
+        let [json, markdown] = paths_for(Path::new("out/clip"));
+        assert_eq!(json, Path::new("out/clip.review.json"));
+        assert_eq!(markdown, Path::new("out/clip.review.md"));
+    }
+}
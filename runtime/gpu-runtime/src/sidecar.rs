@@ -0,0 +1,218 @@
+//! Per-directory `.subtly.toml` overrides for batch folders. Each file under
+//! the input root is transcribed with the job config, overridden by every
+//! sidecar from the root down to the file's own directory.
+
+use crate::errors::RuntimeError;
+use crate::transcript::Format;
+use crate::TranscribeConfig;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use serde_json::json;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const FILE_NAME: &str = ".subtly.toml";
+
+/// Settings a sidecar may change; anything else is rejected so typos do not
+/// go unnoticed.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Overrides {
+    language: Option<String>,
+    translate: Option<bool>,
+    /// Relative paths are resolved against the sidecar's directory.
+    model_path: Option<String>,
+    vad_model_path: Option<String>,
+    vad_threshold: Option<f32>,
+    vad_min_speech_ms: Option<u32>,
+    vad_min_sil_ms: Option<u32>,
+    vad_pad_ms: Option<u32>,
+    output_formats: Option<Vec<String>>,
+}
+
+impl Overrides {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        let mut overrides: Self = toml::from_str(&text).map_err(|err| RuntimeError::InvalidConfig {
+            path: path.display().to_string(),
+            message: err.message().to_string(),
+        })?;
+        for format in overrides.output_formats.iter().flatten() {
+            Format::parse(format).map_err(|err| RuntimeError::InvalidConfig {
+                path: path.display().to_string(),
+                message: err.to_string(),
+            })?;
+        }
+        let dir = path.parent().unwrap_or(Path::new(""));
+        for model in [&mut overrides.model_path, &mut overrides.vad_model_path]
+            .into_iter()
+            .flatten()
+        {
+            *model = dir.join(&*model).to_string_lossy().into_owned();
+        }
+        Ok(overrides)
+    }
+
+    pub fn apply(self, config: &mut TranscribeConfig) {
+        let Self {
+            language,
+            translate,
+            model_path,
+            vad_model_path,
+            vad_threshold,
+            vad_min_speech_ms,
+            vad_min_sil_ms,
+            vad_pad_ms,
+            output_formats,
+        } = self;
+        fn set<T>(field: &mut T, value: Option<T>) {
+            if let Some(value) = value {
+                *field = value;
+            }
+        }
+        set(&mut config.language, language);
+        set(&mut config.translate, translate);
+        set(&mut config.model_path, model_path);
+        set(&mut config.vad_model_path, vad_model_path);
+        set(&mut config.vad_threshold, vad_threshold);
+        set(&mut config.vad_min_speech_ms, vad_min_speech_ms);
+        set(&mut config.vad_min_sil_ms, vad_min_sil_ms);
+        set(&mut config.vad_pad_ms, vad_pad_ms);
+        set(&mut config.output_formats, output_formats);
+    }
+}
+
+/// Sidecar files that apply to `input`, outermost first. Only directories
+/// between `root` and the file count, so a single-file job has none.
+pub fn files_for(root: &Path, input: &Path) -> Vec<PathBuf> {
+    let Some(dir) = input.parent().filter(|dir| dir.starts_with(root)) else {
+        return Vec::new();
+    };
+    let mut files = dir
+        .ancestors()
+        .take_while(|ancestor| ancestor.starts_with(root))
+        .map(|ancestor| ancestor.join(FILE_NAME))
+        .filter(|file| file.is_file())
+        .collect::<Vec<_>>();
+    files.reverse();
+    files
+}
+
+/// The config `input` is transcribed with, plus the sidecars it came from.
+pub fn effective_config(config: &TranscribeConfig, input: &Path) -> Result<(TranscribeConfig, Vec<PathBuf>)> {
+    let mut effective = config.clone();
+    let sources = files_for(&config.input_path, input);
+    for source in &sources {
+        Overrides::load(source)?.apply(&mut effective);
+    }
+    Ok((effective, sources))
+}
+
+/// The settings sidecars can change, as echoed in the per-file log event.
+pub fn describe(config: &TranscribeConfig) -> serde_json::Value {
+    json!({
+        "language": config.language,
+        "translate": config.translate,
+        "model_path": config.model_path,
+        "vad_model_path": config.vad_model_path,
+        "vad_threshold": config.vad_threshold,
+        "vad_min_speech_ms": config.vad_min_speech_ms,
+        "vad_min_sil_ms": config.vad_min_sil_ms,
+        "vad_pad_ms": config.vad_pad_ms,
+        "output_formats": config.output_formats
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transcribe_config;
+
+    fn config(root: &Path) -> TranscribeConfig {
+        transcribe_config(&json!({
+            "input_path": root.to_string_lossy(),
+            "language": "en",
+            "model_path": "/models/large.bin"
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn layers_sidecars_from_the_root_down() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        let german = root.join("de");
+        let interviews = german.join("interviews");
+        fs::create_dir_all(&interviews).unwrap();
+        fs::write(root.join(FILE_NAME), "vad_threshold = 0.4\noutput_formats = [\"srt\", \"vtt\"]\n").unwrap();
+        fs::write(german.join(FILE_NAME), "language = \"de\"\ntranslate = false\nvad_threshold = 0.5\n").unwrap();
+        fs::write(
+            interviews.join(FILE_NAME),
+            "model_path = \"../models/de.bin\"\nvad_model_path = \"/abs/vad.bin\"\nvad_min_speech_ms = 1\nvad_min_sil_ms = 2\nvad_pad_ms = 3\n",
+        )
+        .unwrap();
+        // Sidecars above the input root are ignored.
+        let (effective, sources) = effective_config(&config(&german), &interviews.join("a.mp4")).unwrap();
+        assert_eq!(sources, vec![german.join(FILE_NAME), interviews.join(FILE_NAME)]);
+        assert_eq!(effective.language, "de");
+        assert!(!effective.translate);
+        assert_eq!(effective.vad_threshold, 0.5);
+        assert_eq!(effective.output_formats, vec!["srt".to_string()]);
+        assert_eq!(
+            Path::new(&effective.model_path),
+            interviews.join("../models/de.bin")
+        );
+        assert_eq!(
+            describe(&effective),
+            json!({
+                "language": "de",
+                "translate": false,
+                "model_path": effective.model_path,
+                "vad_model_path": "/abs/vad.bin",
+                "vad_threshold": 0.5,
+                "vad_min_speech_ms": 1,
+                "vad_min_sil_ms": 2,
+                "vad_pad_ms": 3,
+                "output_formats": ["srt"]
+            })
+        );
+
+        let (effective, sources) = effective_config(&config(root), &root.join("b.mp4")).unwrap();
+        assert_eq!(sources, vec![root.join(FILE_NAME)]);
+        assert_eq!(effective.output_formats, vec!["srt".to_string(), "vtt".to_string()]);
+        assert_eq!(effective.language, "en");
+    }
+
+    #[test]
+    fn single_files_have_no_sidecars() {
+        let temp = tempfile::tempdir().unwrap();
+        let input = temp.path().join("a.mp4");
+        fs::write(temp.path().join(FILE_NAME), "language = \"de\"\n").unwrap();
+        let (effective, sources) = effective_config(&config(&input), &input).unwrap();
+        assert!(sources.is_empty());
+        assert_eq!(effective.language, "en");
+        assert!(files_for(Path::new("/media"), Path::new("a.mp4")).is_empty());
+    }
+
+    #[test]
+    fn rejects_unknown_or_malformed_sidecars() {
+        let temp = tempfile::tempdir().unwrap();
+        let input = temp.path().join("a.mp4");
+        fs::write(temp.path().join(FILE_NAME), "beam_size = 2\n").unwrap();
+        let err = effective_config(&config(temp.path()), &input).unwrap_err();
+        assert_eq!(crate::errors::code_and_data(&err).0, crate::errors::INVALID_CONFIG);
+        assert!(err.to_string().contains("unknown field `beam_size`"), "{err}");
+
+        fs::write(temp.path().join(FILE_NAME), "language = 3\n").unwrap();
+        let err = effective_config(&config(temp.path()), &input).unwrap_err();
+        assert!(err.to_string().starts_with("Invalid config"), "{err}");
+
+        fs::write(temp.path().join(FILE_NAME), "output_formats = [\"srt\", \"docx\"]\n").unwrap();
+        let err = effective_config(&config(temp.path()), &input).unwrap_err();
+        assert_eq!(crate::errors::code_and_data(&err).0, crate::errors::INVALID_CONFIG);
+        assert!(err.to_string().contains("Unknown output format: docx"), "{err}");
+
+        let err = Overrides::load(temp.path()).unwrap_err();
+        assert!(err.to_string().starts_with("Failed to read"), "{err}");
+    }
+}
@@ -0,0 +1,349 @@
+//! Existing subtitle files, for tools that check or edit delivered subtitles
+//! rather than write new ones. SRT/VTT files are read as documents of cues:
+//! blocks that are not cues (the VTT header, `NOTE`/`STYLE`/`REGION` blocks,
+//! anything malformed) are kept verbatim so a document renders back as it was
+//! read. The other timed output formats are read back into segments.
+
+use crate::errors::RuntimeError;
+use crate::transcript::{self, Format, Segment};
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Document {
+    pub format: Format,
+    pub blocks: Vec<Block>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Block {
+    Cue(Cue),
+    Other {
+        text: String,
+        /// 1-based line the block starts on.
+        line: usize,
+        /// Why the block is not a valid cue, or `None` for VTT metadata.
+        malformed: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cue {
+    /// SRT number or VTT cue identifier.
+    pub id: Option<String>,
+    pub start_ms: i64,
+    pub end_ms: i64,
+    /// Anything after the end timestamp, e.g. VTT cue settings.
+    pub settings: String,
+    pub lines: Vec<String>,
+    /// 1-based line of the timing line.
+    pub line: usize,
+}
+
+impl Cue {
+    /// Visible characters per line, without markup such as `<i>`.
+    pub fn visible_lines(&self) -> Vec<String> {
+        self.lines.iter().map(|line| strip_tags(line)).collect()
+    }
+}
+
+fn strip_tags(line: &str) -> String {
+    let mut visible = String::new();
+    let mut in_tag = false;
+    for c in line.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            c if !in_tag => visible.push(c),
+            _ => {}
+        }
+    }
+    visible
+}
+
+impl Document {
+    /// Reads an `.srt` or `.vtt` file.
+    pub fn load(path: &Path) -> Result<Self> {
+        let format = format_of(path)?;
+        if !matches!(format, Format::Srt | Format::Vtt) {
+            return Err(RuntimeError::InvalidParams(format!(
+                "Unsupported subtitle file: {} (expected .srt or .vtt)",
+                path.display()
+            ))
+            .into());
+        }
+        Ok(Self::parse(&read(path)?, format))
+    }
+
+    pub fn parse(text: &str, format: Format) -> Self {
+        let text = text.trim_start_matches('\u{feff}').replace("\r\n", "\n");
+        let mut blocks = Vec::new();
+        let mut block: Vec<&str> = Vec::new();
+        let mut start = 0;
+        for (index, line) in text.split('\n').chain([""]).enumerate() {
+            if line.trim().is_empty() {
+                if !block.is_empty() {
+                    blocks.push(parse_block(&block, start + 1, format));
+                    block.clear();
+                }
+            } else {
+                if block.is_empty() {
+                    start = index;
+                }
+                block.push(line.trim_end());
+            }
+        }
+        Self { format, blocks }
+    }
+
+    pub fn cues(&self) -> impl Iterator<Item = &Cue> {
+        self.blocks.iter().filter_map(|block| match block {
+            Block::Cue(cue) => Some(cue),
+            Block::Other { .. } => None,
+        })
+    }
+
+    pub fn cues_mut(&mut self) -> impl Iterator<Item = &mut Cue> {
+        self.blocks.iter_mut().filter_map(|block| match block {
+            Block::Cue(cue) => Some(cue),
+            Block::Other { .. } => None,
+        })
+    }
+
+    pub fn render(&self) -> String {
+        let separator = if self.format == Format::Vtt { '.' } else { ',' };
+        let blocks = self
+            .blocks
+            .iter()
+            .map(|block| match block {
+                Block::Cue(cue) => {
+                    let mut out = String::new();
+                    if let Some(id) = &cue.id {
+                        out.push_str(&format!("{id}\n"));
+                    }
+                    out.push_str(&format!(
+                        "{} --> {}{}",
+                        transcript::timestamp(cue.start_ms, separator),
+                        transcript::timestamp(cue.end_ms, separator),
+                        cue.settings
+                    ));
+                    for line in &cue.lines {
+                        out.push_str(&format!("\n{line}"));
+                    }
+                    out
+                }
+                Block::Other { text, .. } => text.clone(),
+            })
+            .collect::<Vec<_>>();
+        if blocks.is_empty() {
+            return String::new();
+        }
+        blocks.join("\n\n") + "\n"
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        fs::write(path, self.render()).with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+/// The output format a file was written in, from its extension.
+pub fn format_of(path: &Path) -> Result<Format> {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let extension = match name.rsplit_once('.') {
+        _ if name.ends_with(".words.json") => "words",
+        Some((_, extension)) => extension,
+        None => "",
+    };
+    Format::parse(extension).map_err(|_| {
+        RuntimeError::InvalidParams(format!(
+            "Unsupported subtitle file: {} (expected .srt, .vtt, .json, .csv, .words.json or .txt)",
+            path.display()
+        ))
+        .into()
+    })
+}
+
+fn read(path: &Path) -> Result<String> {
+    if !path.is_file() {
+        return Err(RuntimeError::MissingInput {
+            message: format!("Subtitle file not found: {}", path.display()),
+            path: path.display().to_string(),
+        }
+        .into());
+    }
+    fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))
+}
+
+/// Reads a `.json`, `.csv` or `.words.json` file back into segments. SRT and
+/// VTT files are read as a [`Document`]; `.txt` has no timings.
+pub fn load_segments(path: &Path) -> Result<Vec<Segment>> {
+    let invalid = |message: String| RuntimeError::InvalidParams(message).into();
+    let format = format_of(path)?;
+    let parse = match format {
+        Format::Json => |text: &str| Ok(transcript::parse(text)?.segments),
+        Format::Csv => transcript::parse_csv,
+        Format::Words => transcript::parse_words,
+        Format::Txt => return Err(invalid(format!("{} has no timings", path.display()))),
+        Format::Srt | Format::Vtt => {
+            return Err(invalid(format!("{} is read as a subtitle document", path.display())))
+        }
+    };
+    parse(&read(path)?).map_err(|err| invalid(format!("Invalid {} file {}: {err}", format.extension(), path.display())))
+}
+
+fn parse_block(lines: &[&str], line: usize, format: Format) -> Block {
+    let other = |malformed: Option<&str>| Block::Other {
+        text: lines.join("\n"),
+        line,
+        malformed: malformed.map(str::to_string),
+    };
+    if format == Format::Vtt && ["WEBVTT", "NOTE", "STYLE", "REGION"].iter().any(|word| lines[0].starts_with(word)) {
+        return other(None);
+    }
+    let Some(timing) = lines.iter().take(2).position(|line| line.contains("-->")) else {
+        return other(Some("no timing line"));
+    };
+    let Some((start, end, settings)) = parse_timing(lines[timing]) else {
+        return other(Some("invalid timing line"));
+    };
+    Block::Cue(Cue {
+        id: (timing == 1).then(|| lines[0].to_string()),
+        start_ms: start,
+        end_ms: end,
+        settings,
+        lines: lines[timing + 1..].iter().map(|line| line.to_string()).collect(),
+        line: line + timing,
+    })
+}
+
+/// `00:00:01,000 --> 00:00:02,500 align:start` as start, end and settings.
+/// VTT's short `01.000` / `00:01.000` forms are accepted too.
+fn parse_timing(line: &str) -> Option<(i64, i64, String)> {
+    let (start, rest) = line.split_once("-->")?;
+    let rest = rest.trim_start();
+    let end_len = rest.find(char::is_whitespace).unwrap_or(rest.len());
+    let (end, settings) = rest.split_at(end_len);
+    Some((timestamp(start.trim())?, timestamp(end)?, settings.to_string()))
+}
+
+fn timestamp(text: &str) -> Option<i64> {
+    let full = match text.matches(':').count() {
+        0 => format!("00:00:{text}"),
+        1 => format!("00:{text}"),
+        _ => text.to_string(),
+    };
+    crate::timestamp_to_ms(&full).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SRT: &str = "\u{feff}1\r\n00:00:01,000 --> 00:00:02,500\r\n<i>Hello</i> there\r\nsecond line\r\n\r\n\
+                       2\r\n00:00:03,000 --> 00:00:04,000 X1:10\r\nBye.\r\n\r\n\r\nstray text\r\n";
+
+    #[test]
+    fn parses_srt_cues_and_keeps_the_rest() {
+        let document = Document::parse(SRT, Format::Srt);
+        let cues = document.cues().collect::<Vec<_>>();
+        assert_eq!(cues.len(), 2);
+        assert_eq!(cues[0].id.as_deref(), Some("1"));
+        assert_eq!((cues[0].start_ms, cues[0].end_ms, cues[0].line), (1000, 2500, 2));
+        assert_eq!(cues[0].visible_lines(), ["Hello there", "second line"]);
+        assert_eq!(cues[1].settings, " X1:10");
+        assert_eq!(
+            document.render(),
+            "1\n00:00:01,000 --> 00:00:02,500\n<i>Hello</i> there\nsecond line\n\n\
+             2\n00:00:03,000 --> 00:00:04,000 X1:10\nBye.\n\nstray text\n"
+        );
+        assert_eq!(
+            document.blocks[2],
+            Block::Other {
+                text: "stray text".to_string(),
+                line: 11,
+                malformed: Some("no timing line".to_string())
+            }
+        );
+    }
+
+    #[test]
+    fn parses_vtt_headers_ids_and_short_timestamps() {
+        let text = "WEBVTT - demo\n\nNOTE keep me\n\nintro\n00:01.000 --> 00:02.000 align:start\nHi\n\n\
+                    02.000 --> 03.500\nThere\n\n00:00:04.000 --> soon\nbroken\n";
+        let document = Document::parse(text, Format::Vtt);
+        let cues = document.cues().collect::<Vec<_>>();
+        assert_eq!(cues[0].id.as_deref(), Some("intro"));
+        assert_eq!((cues[0].start_ms, cues[0].end_ms), (1000, 2000));
+        assert_eq!(cues[0].settings, " align:start");
+        assert_eq!((cues[1].id.clone(), cues[1].start_ms, cues[1].end_ms), (None, 2000, 3500));
+        assert!(matches!(&document.blocks[0], Block::Other { malformed: None, .. }));
+        assert!(matches!(&document.blocks[4], Block::Other { malformed: Some(why), .. } if why == "invalid timing line"));
+        assert_eq!(
+            document.render(),
+            "WEBVTT - demo\n\nNOTE keep me\n\nintro\n00:00:01.000 --> 00:00:02.000 align:start\nHi\n\n\
+             00:00:02.000 --> 00:00:03.500\nThere\n\n00:00:04.000 --> soon\nbroken\n"
+        );
+        assert_eq!(Document::parse("", Format::Vtt).render(), "");
+    }
+
+    #[test]
+    fn loads_only_subtitle_files() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("clip.SRT");
+        fs::write(&path, SRT).unwrap();
+        let document = Document::load(&path).unwrap();
+        assert_eq!(document.format, Format::Srt);
+
+        let copy = temp.path().join("copy.srt");
+        document.write(&copy).unwrap();
+        assert_eq!(Document::load(&copy).unwrap().render(), document.render());
+        let err = document.write(temp.path()).unwrap_err();
+        assert!(err.to_string().starts_with("Failed to write"), "{err}");
+
+        let err = Document::load(&temp.path().join("missing.vtt")).unwrap_err();
+        assert_eq!(crate::errors::code_and_data(&err).0, crate::errors::MISSING_INPUT);
+        for name in ["clip.txt", "clip.csv", "clip", "clip.docx"] {
+            let err = Document::load(&temp.path().join(name)).unwrap_err();
+            assert_eq!(crate::errors::code_and_data(&err).0, crate::errors::INVALID_PARAMS);
+        }
+        let unreadable = temp.path().join("binary.srt");
+        fs::write(&unreadable, [0xff, 0xfe, 0x00]).unwrap();
+        let err = Document::load(&unreadable).unwrap_err();
+        assert!(err.to_string().starts_with("Failed to read"), "{err}");
+    }
+
+    #[test]
+    fn loads_segments_from_timed_formats() {
+        let temp = tempfile::tempdir().unwrap();
+        let formats = [("a.JSON", Format::Json), ("a.csv", Format::Csv), ("a.words.json", Format::Words)];
+        let segments = vec![crate::transcript::test_segment(1000, 2000, "One")];
+        for (name, format) in formats {
+            let path = temp.path().join(name);
+            assert_eq!(format_of(&path).unwrap(), format);
+            fs::write(&path, format.render(&segments)).unwrap();
+            assert_eq!(crate::transcript::test_spans(&load_segments(&path).unwrap()), [(1000, 2000)]);
+        }
+        assert_eq!(format_of(Path::new("a.vtt")).unwrap(), Format::Vtt);
+
+        let messages = [
+            ("a.txt", "has no timings"),
+            ("a.srt", "is read as a subtitle document"),
+            ("a", "Unsupported subtitle file"),
+        ];
+        for (name, message) in messages {
+            let err = load_segments(&temp.path().join(name)).unwrap_err();
+            assert_eq!(crate::errors::code_and_data(&err).0, crate::errors::INVALID_PARAMS);
+            assert!(err.to_string().contains(message), "{err}");
+        }
+        fs::write(temp.path().join("bad.csv"), "1,2\n").unwrap();
+        let err = load_segments(&temp.path().join("bad.csv")).unwrap_err();
+        assert_eq!(crate::errors::code_and_data(&err).0, crate::errors::INVALID_PARAMS);
+        assert!(err.to_string().starts_with("Invalid csv file"), "{err}");
+        let err = load_segments(&temp.path().join("missing.csv")).unwrap_err();
+        assert_eq!(crate::errors::code_and_data(&err).0, crate::errors::MISSING_INPUT);
+    }
+}
@@ -0,0 +1,232 @@
+//! Finds where a subtitle file's cues line up with the speech in a recording:
+//! one offset for the whole file, or with `piecewise` a run of offsets that
+//! changes where the cut was edited. Every candidate offset is scored by how
+//! much cue time lands on speech.
+
+use serde::Serialize;
+
+/// Offsets are tried in steps of this.
+pub const STEP_MS: i64 = 10;
+
+/// Speech overlap a change of offset has to gain to be worth it, so that
+/// noise does not split a file into many pieces.
+const SWITCH_PENALTY_MS: i64 = 2000;
+
+/// Consecutive cues (1-based, inclusive) that move by the same offset.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Piece {
+    pub first_cue: usize,
+    pub last_cue: usize,
+    pub offset_ms: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Alignment {
+    pub pieces: Vec<Piece>,
+    /// Share of cue time over speech before and after moving the cues.
+    pub coverage_before: f64,
+    pub coverage_after: f64,
+}
+
+impl Alignment {
+    /// The offset for each cue, in order.
+    pub fn offsets(&self) -> impl Iterator<Item = i64> + '_ {
+        self.pieces
+            .iter()
+            .flat_map(|piece| std::iter::repeat_n(piece.offset_ms, piece.last_cue - piece.first_cue + 1))
+    }
+}
+
+/// Speech regions from `whisper-vad-speech-segments` output, whose lines read
+/// `Speech segment 0: start = 0.29, end = 2.21` (seconds).
+pub fn parse_vad_segments(output: &str) -> Vec<(i64, i64)> {
+    let seconds = |line: &str, key: &str| -> Option<i64> {
+        let value = line.split(key).nth(1)?.split(',').next()?.trim();
+        Some((value.parse::<f64>().ok()? * 1000.0).round() as i64)
+    };
+    output
+        .lines()
+        .filter_map(|line| Some((seconds(line, "start =")?, seconds(line, "end =")?)))
+        .collect()
+}
+
+/// Speech time within a span, from prefix sums over [`STEP_MS`] slots.
+struct Timeline {
+    prefix: Vec<i64>,
+}
+
+impl Timeline {
+    fn new(speech: &[(i64, i64)]) -> Self {
+        let slots = speech.iter().map(|&(_, end)| slot(end)).max().unwrap_or(0).max(0) as usize;
+        let mut voiced = vec![false; slots];
+        for &(start, end) in speech {
+            let (first, last) = (slot(start).clamp(0, slots as i64), slot(end).clamp(0, slots as i64));
+            voiced[first as usize..last.max(first) as usize].fill(true);
+        }
+        let mut prefix = vec![0; slots + 1];
+        for (index, voiced) in voiced.into_iter().enumerate() {
+            prefix[index + 1] = prefix[index] + i64::from(voiced);
+        }
+        Self { prefix }
+    }
+
+    fn covered(&self, start_ms: i64, end_ms: i64) -> i64 {
+        let len = self.prefix.len() as i64 - 1;
+        let (first, last) = (slot(start_ms).clamp(0, len), slot(end_ms).clamp(0, len));
+        if last <= first {
+            return 0;
+        }
+        (self.prefix[last as usize] - self.prefix[first as usize]) * STEP_MS
+    }
+}
+
+fn slot(ms: i64) -> i64 {
+    ms.div_euclid(STEP_MS)
+}
+
+/// The index of the highest score, preferring the smallest offset on ties.
+fn best(scores: &[i64], offsets: &[i64]) -> usize {
+    (0..scores.len())
+        .max_by_key(|&index| (scores[index], -offsets[index].abs()))
+        .unwrap_or(0)
+}
+
+/// Aligns `cues` (`(start_ms, end_ms)`, in order) to `speech`, trying offsets
+/// up to `max_offset_ms` either way.
+pub fn align(cues: &[(i64, i64)], speech: &[(i64, i64)], max_offset_ms: i64, piecewise: bool) -> Alignment {
+    let timeline = Timeline::new(speech);
+    let steps = max_offset_ms.max(0) / STEP_MS;
+    let offsets = (-steps..=steps).map(|step| step * STEP_MS).collect::<Vec<_>>();
+    let score = |cue: (i64, i64), offset: i64| timeline.covered(cue.0 + offset, cue.1 + offset);
+
+    let choices = if piecewise {
+        // dp[k]: best total for the cues so far with the last one at
+        // offsets[k]; switching from the best offset costs the penalty.
+        let mut dp = vec![0; offsets.len()];
+        let mut switched = vec![false; cues.len() * offsets.len()];
+        let mut previous_best = vec![0; cues.len()];
+        for (index, &cue) in cues.iter().enumerate() {
+            let from = best(&dp, &offsets);
+            previous_best[index] = from;
+            let switch = dp[from] - SWITCH_PENALTY_MS;
+            for (k, offset) in offsets.iter().enumerate() {
+                if index > 0 && switch > dp[k] {
+                    dp[k] = switch;
+                    switched[index * offsets.len() + k] = true;
+                }
+                dp[k] += score(cue, *offset);
+            }
+        }
+        let mut k = best(&dp, &offsets);
+        let mut choices = vec![0; cues.len()];
+        for index in (0..cues.len()).rev() {
+            choices[index] = offsets[k];
+            if switched[index * offsets.len() + k] {
+                k = previous_best[index];
+            }
+        }
+        choices
+    } else {
+        let totals = offsets
+            .iter()
+            .map(|&offset| cues.iter().map(|&cue| score(cue, offset)).sum())
+            .collect::<Vec<_>>();
+        vec![offsets[best(&totals, &offsets)]; cues.len()]
+    };
+
+    let mut pieces: Vec<Piece> = Vec::new();
+    for (index, &offset_ms) in choices.iter().enumerate() {
+        match pieces.last_mut() {
+            Some(piece) if piece.offset_ms == offset_ms => piece.last_cue = index + 1,
+            _ => pieces.push(Piece {
+                first_cue: index + 1,
+                last_cue: index + 1,
+                offset_ms,
+            }),
+        }
+    }
+    let total = cues.iter().map(|&(start, end)| (end - start).max(0)).sum::<i64>();
+    let coverage = |covered: i64| {
+        if total == 0 {
+            0.0
+        } else {
+            (covered as f64 / total as f64 * 1000.0).round() / 1000.0
+        }
+    };
+    Alignment {
+        pieces,
+        coverage_before: coverage(cues.iter().map(|&cue| score(cue, 0)).sum()),
+        coverage_after: coverage(cues.iter().zip(&choices).map(|(&cue, &offset)| score(cue, offset)).sum()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shifted(cues: &[(i64, i64)], offset: i64) -> Vec<(i64, i64)> {
+        cues.iter().map(|&(start, end)| (start + offset, end + offset)).collect()
+    }
+
+    const FIRST: &[(i64, i64)] = &[(1000, 2200), (3500, 4300), (6100, 7900), (9000, 9600)];
+    const SECOND: &[(i64, i64)] = &[(20_000, 21_500), (23_800, 24_400), (26_000, 28_200), (31_000, 31_700)];
+
+    #[test]
+    fn finds_a_global_offset() {
+        let speech = shifted(FIRST, 2500);
+        let alignment = align(FIRST, &speech, 10_000, false);
+        assert_eq!(
+            alignment.pieces,
+            [Piece {
+                first_cue: 1,
+                last_cue: 4,
+                offset_ms: 2500
+            }]
+        );
+        assert_eq!(alignment.coverage_after, 1.0);
+        assert!(alignment.coverage_before < 0.5, "{alignment:?}");
+        assert_eq!(alignment.offsets().collect::<Vec<_>>(), [2500; 4]);
+
+        // Offsets stay within reach, and with nothing to align to cues stay put.
+        assert!(align(FIRST, &speech, 1000, false).pieces[0].offset_ms.abs() <= 1000);
+        assert_eq!(align(FIRST, &[], 10_000, false).pieces[0].offset_ms, 0);
+        let empty = align(&[], &speech, 10_000, true);
+        assert_eq!((empty.pieces.len(), empty.coverage_after), (0, 0.0));
+    }
+
+    #[test]
+    fn finds_offsets_around_an_edit() {
+        let cues = [FIRST, SECOND].concat();
+        let speech = [shifted(FIRST, 1000), shifted(SECOND, 6000)].concat();
+        let alignment = align(&cues, &speech, 10_000, true);
+        assert_eq!(
+            alignment.pieces,
+            [
+                Piece {
+                    first_cue: 1,
+                    last_cue: 4,
+                    offset_ms: 1000
+                },
+                Piece {
+                    first_cue: 5,
+                    last_cue: 8,
+                    offset_ms: 6000
+                }
+            ]
+        );
+        assert_eq!(alignment.coverage_after, 1.0);
+        let global = align(&cues, &speech, 10_000, false);
+        assert_eq!(global.pieces.len(), 1);
+        assert!(global.coverage_after < 0.9, "{global:?}");
+    }
+
+    #[test]
+    fn reads_vad_tool_output() {
+        let output = "whisper_vad_init: loading model\nDetected 2 speech segments:\n\
+                      Speech segment 0: start = 0.29, end = 2.21\nSpeech segment 1: start = 3.30, end = 10.00\n";
+        assert_eq!(parse_vad_segments(output), [(290, 2210), (3300, 10_000)]);
+        assert!(parse_vad_segments("start = x, end = 1").is_empty());
+        let timeline = Timeline::new(&[(-50, 25), (40, 30)]);
+        assert_eq!((timeline.covered(0, 100), timeline.covered(-100, 10), timeline.covered(50, 40)), (20, 10, 0));
+    }
+}
@@ -0,0 +1,724 @@
+//! The runtime's subtitle model. whisper-cli's full JSON transcript (`-ojf`)
+//! is parsed into [`Segment`]s of [`Word`]s, post-processed here (wrapping to
+//! `max_len_chars`, merging repeats) and written to every output format, so a
+//! cached transcript can be re-rendered with new settings. Token probabilities
+//! are kept as confidences for words and segments.
+
+use crate::errors::RuntimeError;
+use anyhow::{Context, Result};
+use serde::de::{Deserializer, Visitor};
+use serde::Deserialize;
+use serde_json::json;
+use std::fmt;
+
+/// A parsed whisper-cli transcript.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transcript {
+    /// The spoken language whisper-cli detected or was given.
+    pub language: Option<String>,
+    pub segments: Vec<Segment>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Segment {
+    pub start_ms: i64,
+    pub end_ms: i64,
+    pub text: String,
+    pub words: Vec<Word>,
+}
+
+/// A word as whisper-cli timed it: one or more tokens, the first of which
+/// usually starts with a space.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Word {
+    pub text: String,
+    pub start_ms: i64,
+    pub end_ms: i64,
+    pub tokens: Vec<Token>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub text: String,
+    pub start_ms: i64,
+    pub end_ms: i64,
+    /// whisper-cli's probability for this token, 0 to 1.
+    pub p: f64,
+}
+
+impl Word {
+    fn new(token: Token) -> Self {
+        Self {
+            text: token.text.clone(),
+            start_ms: token.start_ms,
+            end_ms: token.end_ms,
+            tokens: vec![token],
+        }
+    }
+
+    fn push(&mut self, token: Token) {
+        self.text.push_str(&token.text);
+        self.end_ms = token.end_ms;
+        self.tokens.push(token);
+    }
+
+    /// A word is as doubtful as its least certain token.
+    pub fn confidence(&self) -> f64 {
+        self.tokens.iter().map(|token| token.p).fold(1.0, f64::min)
+    }
+}
+
+impl Segment {
+    fn from_words(words: Vec<Word>) -> Self {
+        Self {
+            start_ms: words[0].start_ms,
+            end_ms: words[words.len() - 1].end_ms,
+            text: words.iter().map(|word| word.text.as_str()).collect(),
+            words,
+        }
+    }
+
+    /// Mean and minimum token probability; `None` when whisper-cli gave no
+    /// tokens for the segment.
+    pub fn confidence(&self) -> Option<(f64, f64)> {
+        let probabilities = self
+            .words
+            .iter()
+            .flat_map(|word| &word.tokens)
+            .map(|token| token.p)
+            .collect::<Vec<_>>();
+        if probabilities.is_empty() {
+            return None;
+        }
+        let average = probabilities.iter().sum::<f64>() / probabilities.len() as f64;
+        Some((average, probabilities.iter().copied().fold(1.0, f64::min)))
+    }
+
+    /// The segment with its words and confidences, as written to `words`
+    /// output and returned in the `transcribe` result.
+    pub fn to_json(&self) -> serde_json::Value {
+        let confidence = self.confidence();
+        json!({
+            "start_ms": self.start_ms,
+            "end_ms": self.end_ms,
+            "text": self.text.trim(),
+            "avg_confidence": confidence.map(|(average, _)| round(average)),
+            "min_confidence": confidence.map(|(_, min)| round(min)),
+            "words": self
+                .words
+                .iter()
+                .map(|word| {
+                    json!({
+                        "text": word.text.trim(),
+                        "start_ms": word.start_ms,
+                        "end_ms": word.end_ms,
+                        "confidence": round(word.confidence())
+                    })
+                })
+                .collect::<Vec<_>>()
+        })
+    }
+}
+
+fn round(p: f64) -> f64 {
+    (p * 1000.0).round() / 1000.0
+}
+
+/// Output formats, each written from the same segments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Srt,
+    Vtt,
+    Json,
+    Csv,
+    Txt,
+    /// Segments with word timings and confidences.
+    Words,
+}
+
+impl Format {
+    pub fn parse(name: &str) -> Result<Self> {
+        Ok(match name {
+            "srt" => Self::Srt,
+            "vtt" => Self::Vtt,
+            "json" => Self::Json,
+            "csv" => Self::Csv,
+            "txt" => Self::Txt,
+            "words" => Self::Words,
+            _ => {
+                return Err(RuntimeError::InvalidParams(format!(
+                    "Unknown output format: {name} (expected one of {})",
+                    crate::capabilities::OUTPUT_FORMATS.join(", ")
+                ))
+                .into())
+            }
+        })
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Srt => "srt",
+            Self::Vtt => "vtt",
+            Self::Json => "json",
+            Self::Csv => "csv",
+            Self::Txt => "txt",
+            Self::Words => "words.json",
+        }
+    }
+
+    pub fn render(self, segments: &[Segment]) -> String {
+        match self {
+            Self::Srt => cues(segments, ',', |index| format!("{}\n", index + 1), ""),
+            Self::Vtt => cues(segments, '.', |_| String::new(), "WEBVTT\n\n"),
+            Self::Json => {
+                let transcription = segments
+                    .iter()
+                    .map(|segment| {
+                        json!({
+                            "timestamps": {
+                                "from": timestamp(segment.start_ms, ','),
+                                "to": timestamp(segment.end_ms, ',')
+                            },
+                            "offsets": { "from": segment.start_ms, "to": segment.end_ms },
+                            "text": segment.text.trim()
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                serde_json::to_string_pretty(&json!({ "transcription": transcription })).unwrap() + "\n"
+            }
+            Self::Csv => {
+                let mut out = String::from("start,end,text\n");
+                for segment in segments {
+                    out.push_str(&format!(
+                        "{},{},\"{}\"\n",
+                        segment.start_ms,
+                        segment.end_ms,
+                        segment.text.trim().replace('"', "\"\"")
+                    ));
+                }
+                out
+            }
+            Self::Txt => segments
+                .iter()
+                .map(|segment| format!("{}\n", segment.text.trim().replace('\n', " ")))
+                .collect(),
+            Self::Words => {
+                let segments = segments.iter().map(Segment::to_json).collect::<Vec<_>>();
+                serde_json::to_string_pretty(&json!({ "segments": segments })).unwrap() + "\n"
+            }
+        }
+    }
+}
+
+/// SRT/VTT cues separated by blank lines.
+fn cues(segments: &[Segment], separator: char, label: impl Fn(usize) -> String, header: &str) -> String {
+    let cues = segments
+        .iter()
+        .enumerate()
+        .map(|(index, segment)| {
+            format!(
+                "{}{} --> {}\n{}\n",
+                label(index),
+                timestamp(segment.start_ms, separator),
+                timestamp(segment.end_ms, separator),
+                segment.text.trim()
+            )
+        })
+        .collect::<Vec<_>>();
+    format!("{header}{}", cues.join("\n"))
+}
+
+#[derive(Deserialize)]
+struct RawTranscript {
+    #[serde(default)]
+    result: Option<RawResult>,
+    transcription: Vec<RawSegment>,
+}
+
+#[derive(Deserialize)]
+struct RawResult {
+    language: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RawSegment {
+    offsets: Offsets,
+    #[serde(deserialize_with = "raw_bytes")]
+    text: Vec<u8>,
+    #[serde(default)]
+    tokens: Vec<RawToken>,
+}
+
+#[derive(Deserialize)]
+struct RawToken {
+    #[serde(deserialize_with = "raw_bytes")]
+    text: Vec<u8>,
+    offsets: Offsets,
+    p: f64,
+}
+
+#[derive(Deserialize)]
+struct Offsets {
+    from: i64,
+    to: i64,
+}
+
+/// A JSON string as bytes. whisper-cli writes each token's bytes as they
+/// are, so a character split across two tokens is invalid UTF-8 in both.
+fn raw_bytes<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Vec<u8>, D::Error> {
+    struct Bytes;
+
+    impl Visitor<'_> for Bytes {
+        type Value = Vec<u8>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a string")
+        }
+
+        fn visit_bytes<E>(self, bytes: &[u8]) -> std::result::Result<Vec<u8>, E> {
+            Ok(bytes.to_vec())
+        }
+
+        fn visit_str<E>(self, text: &str) -> std::result::Result<Vec<u8>, E> {
+            Ok(text.as_bytes().to_vec())
+        }
+    }
+
+    deserializer.deserialize_bytes(Bytes)
+}
+
+/// Decodes token texts, moving a character's leading bytes into the token
+/// that completes it so every token (and so every word) is valid UTF-8.
+fn decode_tokens(raw: Vec<RawToken>) -> Vec<Token> {
+    let mut tokens: Vec<Token> = Vec::new();
+    let mut carry = Vec::new();
+    for token in raw {
+        if token.text.starts_with(b"[_") && token.text.ends_with(b"]") {
+            continue;
+        }
+        let mut bytes = std::mem::take(&mut carry);
+        bytes.extend_from_slice(&token.text);
+        if let Err(err) = std::str::from_utf8(&bytes) {
+            if err.error_len().is_none() {
+                carry = bytes.split_off(err.valid_up_to());
+            }
+        }
+        tokens.push(Token {
+            text: String::from_utf8_lossy(&bytes).into_owned(),
+            start_ms: token.offsets.from,
+            end_ms: token.offsets.to,
+            p: token.p,
+        });
+    }
+    if let Some(last) = tokens.last_mut() {
+        last.text.push_str(&String::from_utf8_lossy(&carry));
+    }
+    tokens
+}
+
+/// Parses whisper-cli JSON, dropping special tokens such as `[_BEG_]` and
+/// joining the rest into words.
+pub fn parse(json: impl AsRef<[u8]>) -> Result<Transcript> {
+    let raw: RawTranscript = serde_json::from_slice(json.as_ref()).context("Invalid whisper-cli transcript")?;
+    let segments = raw
+        .transcription
+        .into_iter()
+        .map(|segment| {
+            let mut words: Vec<Word> = Vec::new();
+            for token in decode_tokens(segment.tokens) {
+                match words.last_mut() {
+                    Some(word) if !token.text.starts_with(' ') => word.push(token),
+                    _ => words.push(Word::new(token)),
+                }
+            }
+            Segment {
+                start_ms: segment.offsets.from,
+                end_ms: segment.offsets.to,
+                text: String::from_utf8_lossy(&segment.text).into_owned(),
+                words,
+            }
+        })
+        .collect();
+    Ok(Transcript {
+        language: raw.result.and_then(|result| result.language),
+        segments,
+    })
+}
+
+/// Reads back the `csv` output: `start,end,text` rows in milliseconds, with
+/// quoted text that may span lines.
+pub fn parse_csv(text: &str) -> Result<Vec<Segment>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.trim_start_matches('\u{feff}').chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => row.push(std::mem::take(&mut field)),
+            '\r' if !quoted => {}
+            '\n' if !quoted => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            c => field.push(c),
+        }
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    rows.into_iter()
+        .enumerate()
+        .filter(|(index, row)| !(*index == 0 && row[0].trim() == "start"))
+        .filter(|(_, row)| row.iter().any(|field| !field.trim().is_empty()))
+        .map(|(index, row)| match row.as_slice() {
+            [start, end, text] => Ok(Segment {
+                start_ms: start.trim().parse().with_context(|| format!("Invalid start on CSV row {}", index + 1))?,
+                end_ms: end.trim().parse().with_context(|| format!("Invalid end on CSV row {}", index + 1))?,
+                text: text.clone(),
+                words: Vec::new(),
+            }),
+            _ => anyhow::bail!("Expected start,end,text on CSV row {}", index + 1),
+        })
+        .collect()
+}
+
+#[derive(Deserialize)]
+struct RawWordsFile {
+    segments: Vec<RawWordsSegment>,
+}
+
+#[derive(Deserialize)]
+struct RawWordsSegment {
+    start_ms: i64,
+    end_ms: i64,
+    text: String,
+    #[serde(default)]
+    words: Vec<RawWord>,
+}
+
+#[derive(Deserialize)]
+struct RawWord {
+    text: String,
+    start_ms: i64,
+    end_ms: i64,
+    confidence: f64,
+}
+
+/// Reads back the `words` output. Each word becomes a single token carrying
+/// its confidence, so a segment's average is then taken over its words.
+pub fn parse_words(json: &str) -> Result<Vec<Segment>> {
+    let raw: RawWordsFile = serde_json::from_str(json)?;
+    Ok(raw
+        .segments
+        .into_iter()
+        .map(|segment| Segment {
+            start_ms: segment.start_ms,
+            end_ms: segment.end_ms,
+            text: segment.text,
+            words: segment
+                .words
+                .into_iter()
+                .map(|word| {
+                    Word::new(Token {
+                        text: format!(" {}", word.text),
+                        start_ms: word.start_ms,
+                        end_ms: word.end_ms,
+                        p: word.confidence,
+                    })
+                })
+                .collect(),
+        })
+        .collect())
+}
+
+/// Splits segments longer than `max_len` characters, like whisper-cli's
+/// `-ml`: between words with `split_on_word`, otherwise between any tokens.
+/// `0` keeps segments whole.
+pub fn wrap(segments: Vec<Segment>, max_len: usize, split_on_word: bool) -> Vec<Segment> {
+    if max_len == 0 {
+        return segments;
+    }
+    let mut wrapped = Vec::new();
+    for segment in segments {
+        if segment.words.is_empty() || segment.text.trim().chars().count() <= max_len {
+            wrapped.push(segment);
+            continue;
+        }
+        let mut line: Vec<Word> = Vec::new();
+        let mut len = 0;
+        for word in segment.words {
+            let pieces = if split_on_word {
+                vec![word]
+            } else {
+                word.tokens.into_iter().map(Word::new).collect()
+            };
+            for (index, piece) in pieces.into_iter().enumerate() {
+                let piece_len = piece.text.chars().count();
+                if !line.is_empty() && len + piece_len > max_len {
+                    wrapped.push(Segment::from_words(std::mem::take(&mut line)));
+                    len = 0;
+                }
+                len += piece_len;
+                // Pieces of one word that stay on the same line stay one word.
+                match line.last_mut() {
+                    Some(last) if index > 0 => piece.tokens.into_iter().for_each(|token| last.push(token)),
+                    _ => line.push(piece),
+                }
+            }
+        }
+        wrapped.push(Segment::from_words(line));
+    }
+    wrapped
+}
+
+/// Drops empty segments and merges a segment into the previous one when it
+/// repeats the same text (ignoring case and spacing) within `merge_gap_ms`.
+pub fn dedup(segments: Vec<Segment>, merge_gap_ms: i64) -> Vec<Segment> {
+    let normalize = |text: &str| text.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase();
+    let mut merged: Vec<Segment> = Vec::new();
+    for segment in segments {
+        if segment.text.trim().is_empty() {
+            continue;
+        }
+        if let Some(previous) = merged.last_mut() {
+            if normalize(&previous.text) == normalize(&segment.text)
+                && segment.start_ms <= previous.end_ms + merge_gap_ms
+            {
+                previous.end_ms = previous.end_ms.max(segment.end_ms);
+                continue;
+            }
+        }
+        merged.push(segment);
+    }
+    merged
+}
+
+pub fn timestamp(ms: i64, separator: char) -> String {
+    let ms = ms.max(0);
+    format!(
+        "{:02}:{:02}:{:02}{separator}{:03}",
+        ms / 3_600_000,
+        ms / 60_000 % 60,
+        ms / 1000 % 60,
+        ms % 1000
+    )
+}
+
+/// A segment without word timings, for tests across the crate.
+#[cfg(test)]
+pub(crate) fn test_segment(start_ms: i64, end_ms: i64, text: &str) -> Segment {
+    Segment {
+        start_ms,
+        end_ms,
+        text: text.to_string(),
+        words: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+pub(crate) fn test_texts(segments: &[Segment]) -> Vec<&str> {
+    segments.iter().map(|segment| segment.text.as_str()).collect()
+}
+
+#[cfg(test)]
+pub(crate) fn test_spans(segments: &[Segment]) -> Vec<(i64, i64)> {
+    segments.iter().map(|segment| (segment.start_ms, segment.end_ms)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRANSCRIPT: &str = r#"{
+        "systeminfo": "AVX = 1",
+        "result": { "language": "en" },
+        "transcription": [
+            {
+                "timestamps": { "from": "00:00:01,000", "to": "00:00:04,000" },
+                "offsets": { "from": 1000, "to": 4000 },
+                "text": " Hello there, \"general\" Kenobi.",
+                "tokens": [
+                    { "text": "[_BEG_]", "offsets": { "from": 1000, "to": 1000 }, "id": 50364, "p": 0.9 },
+                    { "text": " Hello", "offsets": { "from": 1000, "to": 1500 }, "id": 1, "p": 0.9 },
+                    { "text": " there", "offsets": { "from": 1500, "to": 2000 }, "id": 2, "p": 0.9 },
+                    { "text": ",", "offsets": { "from": 2000, "to": 2100 }, "id": 3, "p": 0.5 },
+                    { "text": " \"general\"", "offsets": { "from": 2100, "to": 3000 }, "id": 4, "p": 0.9 },
+                    { "text": " Ken", "offsets": { "from": 3000, "to": 3500 }, "id": 5, "p": 0.9 },
+                    { "text": "obi.", "offsets": { "from": 3500, "to": 4000 }, "id": 6, "p": 0.3 },
+                    { "text": "[_TT_200]", "offsets": { "from": 4000, "to": 4000 }, "id": 50565, "p": 0.9 }
+                ]
+            },
+            {
+                "offsets": { "from": 3723004, "to": 3725000 },
+                "text": " Bye."
+            }
+        ]
+    }"#;
+
+    #[test]
+    fn parses_words_without_special_tokens() {
+        let transcript = parse(TRANSCRIPT).unwrap();
+        assert_eq!(transcript.language.as_deref(), Some("en"));
+        let segments = transcript.segments;
+        assert_eq!(segments.len(), 2);
+        assert_eq!(segments[0].start_ms, 1000);
+        let words = segments[0]
+            .words
+            .iter()
+            .map(|word| (word.text.as_str(), word.start_ms, word.end_ms, word.tokens.len()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            words,
+            [
+                (" Hello", 1000, 1500, 1),
+                (" there,", 1500, 2100, 2),
+                (" \"general\"", 2100, 3000, 1),
+                (" Kenobi.", 3000, 4000, 2)
+            ]
+        );
+        assert!(segments[1].words.is_empty());
+
+        assert_eq!(parse(r#"{"transcription": []}"#).unwrap().language, None);
+        let err = parse("{}").unwrap_err();
+        assert_eq!(err.to_string(), "Invalid whisper-cli transcript");
+    }
+
+    #[test]
+    fn wraps_long_segments() {
+        let segments = parse(TRANSCRIPT).unwrap().segments;
+        assert_eq!(wrap(segments.clone(), 0, true), segments);
+        assert_eq!(wrap(segments.clone(), 80, true), segments);
+
+        let words = wrap(segments.clone(), 13, true);
+        assert_eq!(test_texts(&words), [" Hello there,", " \"general\"", " Kenobi.", " Bye."]);
+        assert_eq!((words[1].start_ms, words[1].end_ms), (2100, 3000));
+
+        let tokens = wrap(segments.clone(), 4, false);
+        assert_eq!(test_texts(&tokens), [" Hello", " there", ",", " \"general\"", " Ken", "obi.", " Bye."]);
+        let joined = wrap(segments, 14, false);
+        assert_eq!(test_texts(&joined), [" Hello there,", " \"general\" Ken", "obi.", " Bye."]);
+        assert_eq!(joined[0].words.len(), 2);
+        assert_eq!(joined[0].words[1].tokens.len(), 2);
+    }
+
+    #[test]
+    fn merges_repeated_segments() {
+        let segments = vec![
+            test_segment(500, 900, " "),
+            test_segment(1000, 2000, "Hello"),
+            test_segment(2100, 3000, " hello "),
+            test_segment(3600, 4000, "hello"),
+            test_segment(4000, 5000, "World"),
+        ];
+        let merged = dedup(segments.clone(), 500);
+        assert_eq!(test_texts(&merged), ["Hello", "hello", "World"]);
+        assert_eq!((merged[0].start_ms, merged[0].end_ms), (1000, 3000));
+        assert_eq!(test_texts(&dedup(segments, 600)), ["Hello", "World"]);
+    }
+
+    #[test]
+    fn renders_every_format() {
+        let segments = parse(TRANSCRIPT).unwrap().segments;
+        let render = |name| Format::parse(name).unwrap().render(&segments);
+        assert_eq!(
+            render("srt"),
+            "1\n00:00:01,000 --> 00:00:04,000\nHello there, \"general\" Kenobi.\n\n\
+             2\n01:02:03,004 --> 01:02:05,000\nBye.\n"
+        );
+        assert_eq!(
+            render("vtt"),
+            "WEBVTT\n\n00:00:01.000 --> 00:00:04.000\nHello there, \"general\" Kenobi.\n\n\
+             01:02:03.004 --> 01:02:05.000\nBye.\n"
+        );
+        assert_eq!(
+            render("csv"),
+            "start,end,text\n1000,4000,\"Hello there, \"\"general\"\" Kenobi.\"\n3723004,3725000,\"Bye.\"\n"
+        );
+        assert_eq!(render("txt"), "Hello there, \"general\" Kenobi.\nBye.\n");
+        let json: serde_json::Value = serde_json::from_str(&render("json")).unwrap();
+        assert_eq!(json["transcription"][1]["timestamps"]["from"], "01:02:03,004");
+        assert_eq!(json["transcription"][1]["offsets"]["to"], 3725000);
+        assert_eq!(json["transcription"][0]["text"], "Hello there, \"general\" Kenobi.");
+        assert_eq!(timestamp(-5, ','), "00:00:00,000");
+        assert_eq!(Format::Srt.render(&[]), "");
+        // Multi-line cues keep their breaks except in plain text.
+        let two_lines = [test_segment(0, 1000, "First line\nsecond line")];
+        assert_eq!(Format::Txt.render(&two_lines), "First line second line\n");
+        assert!(Format::Srt.render(&two_lines).ends_with("\nFirst line\nsecond line\n"));
+    }
+
+    #[test]
+    fn reports_word_timings_and_confidence() {
+        let segments = parse(TRANSCRIPT).unwrap().segments;
+        assert_eq!(segments[0].words[1].confidence(), 0.5);
+        let (average, min) = segments[0].confidence().unwrap();
+        assert!((average - 4.4 / 6.0).abs() < 1e-9, "{average}");
+        assert_eq!(min, 0.3);
+        assert_eq!(segments[1].confidence(), None);
+
+        let json: serde_json::Value = serde_json::from_str(&Format::Words.render(&segments)).unwrap();
+        let first = &json["segments"][0];
+        assert_eq!(first["text"], "Hello there, \"general\" Kenobi.");
+        assert_eq!(first["avg_confidence"], 0.733);
+        assert_eq!(first["min_confidence"], 0.3);
+        assert_eq!(
+            first["words"][3],
+            json!({ "text": "Kenobi.", "start_ms": 3000, "end_ms": 4000, "confidence": 0.3 })
+        );
+        assert_eq!(json["segments"][1]["avg_confidence"], serde_json::Value::Null);
+        assert_eq!(json["segments"][1]["words"], json!([]));
+
+        // Wrapping keeps each word's own tokens and confidence.
+        let wrapped = wrap(segments, 13, true);
+        assert_eq!(wrapped[0].confidence().unwrap().1, 0.5);
+        assert_eq!(wrapped[2].text, " Kenobi.");
+        assert_eq!(wrapped[2].confidence().unwrap().1, 0.3);
+    }
+
+    #[test]
+    fn knows_every_advertised_format() {
+        for name in crate::capabilities::OUTPUT_FORMATS {
+            assert!(Format::parse(name).unwrap().extension().starts_with(name));
+        }
+        let err = Format::parse("docx").unwrap_err();
+        assert_eq!(crate::errors::code_and_data(&err).0, crate::errors::INVALID_PARAMS);
+        assert_eq!(
+            err.to_string(),
+            "Unknown output format: docx (expected one of srt, vtt, json, csv, txt, words)"
+        );
+    }
+
+    #[test]
+    fn reads_back_csv_and_words_output() {
+        let mut segments = parse(TRANSCRIPT).unwrap().segments;
+        segments[1].text = " Two \"quoted\",\nlines.".to_string();
+        let csv = Format::Csv.render(&segments);
+        let parsed = parse_csv(&csv).unwrap();
+        assert_eq!(test_spans(&parsed), test_spans(&segments));
+        assert_eq!(Format::Csv.render(&parsed), csv);
+        assert_eq!(parse_csv("\u{feff}1,2,a\r\n\r\n3,4,\"b\"").unwrap().len(), 2);
+        for (text, message) in [
+            ("start,end,text\nx,1,a\n", "Invalid start on CSV row 2"),
+            ("1,x,a", "Invalid end on CSV row 1"),
+            ("1,2\n", "Expected start,end,text on CSV row 1"),
+        ] {
+            assert_eq!(parse_csv(text).unwrap_err().to_string(), message);
+        }
+
+        let words = Format::Words.render(&segments);
+        let parsed = parse_words(&words).unwrap();
+        assert_eq!(test_spans(&parsed), test_spans(&segments));
+        assert_eq!(parsed[0].words[1].text, " there,");
+        assert_eq!(parsed[0].words[1].confidence(), segments[0].words[1].confidence());
+        let reread = Format::Words.render(&parsed);
+        assert_eq!(reread.matches("\"confidence\"").count(), words.matches("\"confidence\"").count());
+        assert!(parse_words("{}").is_err());
+    }
+}
@@ -39,43 +39,46 @@ is_linux() {
   [[ "$(uname -s)" == "Linux" ]]
 }
 
-whisper_bin_name="whisper-cli"
-if is_windows; then
-  whisper_bin_name="whisper-cli.exe"
-fi
-
-whisper_bin_candidates=()
-if is_windows; then
-  whisper_bin_candidates+=(
-    "$WHISPER_CPP_BUILD_DIR/bin/Release/$whisper_bin_name"
-    "$WHISPER_CPP_BUILD_DIR/bin/$whisper_bin_name"
-  )
-else
-  whisper_bin_candidates+=(
-    "$WHISPER_CPP_BUILD_DIR/bin/$whisper_bin_name"
-    "$WHISPER_CPP_BUILD_DIR/bin/Release/$whisper_bin_name"
-  )
-fi
+# whisper-cli plus the VAD tool sync_subtitles uses to find speech.
+required_bins=("whisper-cli" "whisper-vad-speech-segments")
+
+bin_candidates() {
+  local name="$1"
+  if is_windows; then
+    echo "$WHISPER_CPP_BUILD_DIR/bin/Release/$name.exe"
+    echo "$WHISPER_CPP_BUILD_DIR/bin/$name.exe"
+  else
+    echo "$WHISPER_CPP_BUILD_DIR/bin/$name"
+    echo "$WHISPER_CPP_BUILD_DIR/bin/Release/$name"
+  fi
+}
 
 find_existing_binary() {
   local candidate
-  for candidate in "${whisper_bin_candidates[@]}"; do
+  while IFS= read -r candidate; do
     if [[ -f "$candidate" ]]; then
       echo "$candidate"
       return 0
     fi
-  done
+  done < <(bin_candidates "$1")
   return 1
 }
 
+all_binaries_built() {
+  local name
+  for name in "${required_bins[@]}"; do
+    find_existing_binary "$name" >/dev/null || return 1
+  done
+}
+
 built_binary=""
 
 if [[ -n "${WHISPER_CPP_FORCE_REBUILD:-}" ]]; then
   rm -rf "$WHISPER_CPP_BUILD_DIR"
 fi
 
-if built_binary="$(find_existing_binary)"; then
-  echo "whisper.cpp already built at $built_binary"
+if all_binaries_built; then
+  echo "whisper.cpp already built at $(find_existing_binary whisper-cli)"
   exit 0
 fi
 
@@ -100,6 +103,7 @@ cmake_args=(
   -B "$WHISPER_CPP_BUILD_DIR"
   -S "$WHISPER_CPP_DIR"
   -DCMAKE_BUILD_TYPE=Release
+  -DWHISPER_BUILD_EXAMPLES=ON
 )
 
 if is_macos; then
@@ -129,10 +133,11 @@ cmake "${cmake_args[@]}"
 echo "Building whisper.cpp..."
 cmake --build "$WHISPER_CPP_BUILD_DIR" --config Release -j "$(cpu_count)"
 
-if ! built_binary="$(find_existing_binary)"; then
-  echo "Error: whisper-cli not found. Checked:" >&2
-  printf '  - %s\n' "${whisper_bin_candidates[@]}" >&2
-  exit 1
-fi
-
-echo "whisper.cpp build output: $built_binary"
+for name in "${required_bins[@]}"; do
+  if ! built_binary="$(find_existing_binary "$name")"; then
+    echo "Error: $name not found. Checked:" >&2
+    bin_candidates "$name" | sed 's/^/  - /' >&2
+    exit 1
+  fi
+  echo "whisper.cpp build output: $built_binary"
+done
@@ -125,6 +125,7 @@ async function copyWhisperCli() {
   const isMac = process.platform === 'darwin';
   const isLinux = process.platform === 'linux';
   const whisperCliName = isWindows ? 'whisper-cli.exe' : 'whisper-cli';
+  const vadToolName = isWindows ? 'whisper-vad-speech-segments.exe' : 'whisper-vad-speech-segments';
   const ggmlMetalName = 'ggml-metal.metal';
   const whisperBuildDir = process.env.WHISPER_CPP_BUILD_DIR
     ? path.resolve(process.env.WHISPER_CPP_BUILD_DIR)
@@ -148,6 +149,7 @@ async function copyWhisperCli() {
   const sourceGgmlMetal = sourceGgmlMetalCandidates.find((candidate) => fs.existsSync(candidate));
   const destBinDir = path.join(root, 'resources', 'runtime-assets', 'bin');
   const destWhisperCli = path.join(destBinDir, whisperCliName);
+  const destVadTool = path.join(destBinDir, vadToolName);
   const destGgmlMetal = path.join(destBinDir, ggmlMetalName);
 
   // Ensure destination directory exists
@@ -162,9 +164,18 @@ async function copyWhisperCli() {
   await fsp.copyFile(sourceWhisperCli, destWhisperCli);
   console.log(`Copied whisper-cli to ${destWhisperCli}`);
 
+  // Copy the VAD tool sync_subtitles runs next to whisper-cli
+  const sourceVadTool = path.join(path.dirname(sourceWhisperCli), vadToolName);
+  if (!fs.existsSync(sourceVadTool)) {
+    throw new Error(`${vadToolName} not found next to whisper-cli at ${sourceVadTool}`);
+  }
+  await fsp.copyFile(sourceVadTool, destVadTool);
+  console.log(`Copied ${vadToolName} to ${destVadTool}`);
+
   // Set executable permissions on macOS/Linux
   if (!isWindows) {
     await fsp.chmod(destWhisperCli, 0o755);
+    await fsp.chmod(destVadTool, 0o755);
   }
 
   if (isWindows) {
@@ -233,6 +244,7 @@ async function copyWhisperCli() {
     }
 
     ensureLoaderPathRpath(destWhisperCli);
+    ensureLoaderPathRpath(destVadTool);
   }
 }
 